@@ -0,0 +1,107 @@
+//! A stand-in for `claude` and `codex` that replays scripted replies
+//!
+//! Point `--driver-bin`/`--navigator-bin` at the built example and set
+//! `MOCK_AGENT_FIXTURE` to a JSON file of replies per backend, in the order they
+//! should be given:
+//!
+//! ```json
+//! {"claude": ["I'll add the test", "Added it"], "codex": ["Cover the error path", "ALL_DONE"]}
+//! ```
+//!
+//! A reply that is a string is wrapped in that backend's stream format; a list of
+//! strings is printed as-is, one stream line each. `{"sleep_secs": N, "reply": ...}`
+//! waits N seconds before giving `reply`, for a turn that hangs. Invoked as `exec ...` it speaks
+//! codex's `--json` JSONL, otherwise claude's `--output-format stream-json`.
+//!
+//! Each prompt received is written next to the fixture as `<backend>-<n>.prompt`
+//! (and the other arguments, one per line, as `<backend>-<n>.args`), which is also
+//! how a run knows which reply is next. Call `n` reports session id `mock-<backend>-<n>`.
+//! Concurrent calls take the next replies in whatever order they start.
+
+use serde_json::{json, Value};
+use std::io::Write as _;
+use std::path::Path;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--version") {
+        println!("mock-agent {}", env!("CARGO_PKG_VERSION"));
+        return ExitCode::SUCCESS;
+    }
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("mock-agent: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let backend = if args.first().map(String::as_str) == Some("exec") { "codex" } else { "claude" };
+    let prompt = args.last().ok_or("no prompt given")?;
+
+    let fixture = std::env::var("MOCK_AGENT_FIXTURE").map_err(|_| "MOCK_AGENT_FIXTURE is not set")?;
+    let fixture = Path::new(&fixture);
+    let content = std::fs::read_to_string(fixture).map_err(|e| format!("failed to read {}: {}", fixture.display(), e))?;
+    let script: Value = serde_json::from_str(&content).map_err(|e| format!("failed to parse {}: {}", fixture.display(), e))?;
+
+    let dir = fixture.parent().unwrap_or(Path::new("."));
+    let call = claim_call(dir, backend, prompt)?;
+    std::fs::write(dir.join(format!("{}-{}.args", backend, call)), args[..args.len() - 1].join("\n"))
+        .map_err(|e| e.to_string())?;
+    let session = format!("mock-{}-{}", backend, call);
+
+    let mut reply = script
+        .get(backend)
+        .and_then(|replies| replies.get(call))
+        .ok_or_else(|| format!("no {} reply scripted for call {}", backend, call + 1))?;
+    if let Some(secs) = reply.get("sleep_secs").and_then(Value::as_u64) {
+        std::thread::sleep(std::time::Duration::from_secs(secs));
+        reply = reply.get("reply").ok_or("a sleep_secs reply needs a reply")?;
+    }
+    let lines = match reply {
+        Value::String(text) if backend == "codex" => codex_lines(text, &session),
+        Value::String(text) => claude_lines(text, &session),
+        Value::Array(lines) => lines.iter().map(|l| l.as_str().map(str::to_string).unwrap_or_else(|| l.to_string())).collect(),
+        other => return Err(format!("a reply must be a string or a list of lines, got {}", other)),
+    };
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Take the next call number by creating its prompt file; creation is atomic, so
+/// concurrent runs (--navigators) each get their own
+fn claim_call(dir: &Path, backend: &str, prompt: &str) -> Result<usize, String> {
+    for call in 0.. {
+        let path = dir.join(format!("{}-{}.prompt", backend, call));
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => return file.write_all(prompt.as_bytes()).map(|()| call).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("failed to create {}: {}", path.display(), e)),
+        }
+    }
+    unreachable!()
+}
+
+/// `claude -p --output-format stream-json` for a reply of `text`
+fn claude_lines(text: &str, session: &str) -> Vec<String> {
+    vec![
+        json!({"type": "system", "subtype": "init"}).to_string(),
+        json!({"type": "assistant", "message": {"content": [{"type": "text", "text": text}]}}).to_string(),
+        json!({"type": "result", "result": text, "total_cost_usd": 0.0, "session_id": session}).to_string(),
+    ]
+}
+
+/// `codex exec --json` for a reply of `text`
+fn codex_lines(text: &str, session: &str) -> Vec<String> {
+    vec![
+        json!({"type": "thread.started", "thread_id": session}).to_string(),
+        json!({"type": "turn.started"}).to_string(),
+        json!({"type": "item.completed", "item": {"type": "agent_message", "text": text}}).to_string(),
+        json!({"type": "turn.completed"}).to_string(),
+    ]
+}
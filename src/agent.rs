@@ -0,0 +1,498 @@
+//! The agent CLIs Leonard can seat as driver or navigator
+//!
+//! Each CLI implements `Agent`: the arguments for one turn and how to read its JSON
+//! stream. `run` owns everything they share (spawning, the select loop over interrupts,
+//! timeouts, and output, draining, and reporting failures), so supporting another CLI
+//! means a `Backend` variant and an `Agent` impl, not another copy of the loop.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::output::{Output, Role};
+use crate::transcript::AgentExit;
+use crate::{
+    agent_command, deadline_passed, ensure_working_directory, idle_timed_out, is_sandbox_denied_write, kill_child,
+    limits, log_line, process_claude_line, process_codex_line, truncate_line, turn_timed_out, Args, ClaudeResult,
+    CodexForwarding, CodexResult, DriverAccess, DriverOutputSelect, LineReader, NavigatorSandbox, PreambleFilter,
+};
+
+/// An agent CLI Leonard knows how to drive and parse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Claude,
+    Codex,
+}
+
+impl Backend {
+    /// The CLI's name, which is also its default executable
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Claude => "claude",
+            Backend::Codex => "codex",
+        }
+    }
+
+    pub fn api_key_var(self) -> &'static str {
+        match self {
+            Backend::Claude => "ANTHROPIC_API_KEY",
+            Backend::Codex => "OPENAI_API_KEY",
+        }
+    }
+
+    pub fn base_url_var(self) -> &'static str {
+        match self {
+            Backend::Claude => "ANTHROPIC_BASE_URL",
+            Backend::Codex => "OPENAI_BASE_URL",
+        }
+    }
+
+    pub fn install_hint(self) -> &'static str {
+        match self {
+            Backend::Claude => "install the Claude Code CLI: npm install -g @anthropic-ai/claude-code",
+            Backend::Codex => "install the Codex CLI: npm install -g @openai/codex",
+        }
+    }
+}
+
+/// What one agent run produced for the relay
+#[derive(Debug)]
+pub struct AgentReply {
+    /// The text to forward (for the driver, per --driver-output-select)
+    pub text: String,
+    /// Whether the agent called any tools or ran any commands (--skip-navigator-if-no-changes)
+    pub used_tools: bool,
+    /// The session the agent reported (claude's `session_id`, codex's `thread_id`), to resume by id
+    pub session_id: Option<String>,
+    /// Lines forwarded ahead of `text` but not part of the reply itself (--forward-reasoning, --forward-commands)
+    pub notes: Vec<String>,
+}
+
+/// One agent CLI, set up for a single run in a seat
+pub trait Agent {
+    fn backend(&self) -> Backend;
+
+    /// Add this turn's arguments to `cmd`, which already runs the agent's executable
+    fn add_args(&self, cmd: &mut Command, args: &Args, prompt: &str, is_continuation: bool);
+
+    /// Continue session `id` instead of the --*-session-id one, or else the most recent
+    fn resume(&mut self, id: String);
+
+    /// Handle one stdout line, emitting events to `out`; false if it wasn't part of the stream
+    fn parse_line(&mut self, line: &str, out: &Output) -> bool;
+
+    /// The run's reply once the process has exited, or the error for `failure`, a non-zero exit
+    fn finish(self: Box<Self>, failure: Option<AgentExit>) -> Result<AgentReply>;
+}
+
+/// The agent in `role`'s seat, ready for one run; `access` is what a driver may do
+pub fn for_seat(args: &Args, role: Role, access: DriverAccess) -> Box<dyn Agent> {
+    let session_id = args.session_id(role).map(str::to_string);
+    match (args.backend(role), role) {
+        (Backend::Claude, Role::Driver) => {
+            Box::new(ClaudeAgent::new(role, access, args.driver_output_select, session_id))
+        }
+        // A reviewer doesn't edit, and its tool-result lines are noise to the driver
+        (Backend::Claude, Role::Navigator) => {
+            Box::new(ClaudeAgent::new(role, DriverAccess::ReadOnly, DriverOutputSelect::Prose, session_id))
+        }
+        (Backend::Codex, Role::Driver) => {
+            let sandbox = match access {
+                DriverAccess::Edit => NavigatorSandbox::WorkspaceWrite,
+                DriverAccess::ReadOnly => NavigatorSandbox::ReadOnly,
+            };
+            Box::new(CodexAgent::new(role, sandbox, CodexForwarding::default(), session_id))
+        }
+        // Only a reviewer's failed commands are worth forwarding
+        (Backend::Codex, Role::Navigator) => {
+            Box::new(CodexAgent::new(role, args.navigator_sandbox, CodexForwarding::from_args(args), session_id))
+        }
+    }
+}
+
+/// Log tags for an agent's own lines and its errors
+fn log_tags(role: Role) -> (&'static str, &'static str) {
+    match role {
+        Role::Driver => ("driver", "driver-err"),
+        Role::Navigator => ("navigator", "navigator-err"),
+    }
+}
+
+/// Run `agent` in `role`'s seat for one turn in `cwd` and return its reply
+pub async fn run(
+    args: &Args,
+    out: &Output,
+    mut agent: Box<dyn Agent>,
+    role: Role,
+    cwd: Option<&Path>,
+    prompt: &str,
+    is_continuation: bool,
+) -> Result<AgentReply> {
+    ensure_working_directory(cwd)?;
+    let (tag, err_tag) = log_tags(role);
+    let name = agent.backend().name();
+
+    let mut cmd = agent_command(args.wrapper_for(role), &args.binary_for(role));
+    agent.add_args(&mut cmd, args, prompt, is_continuation);
+
+    if let Some(dir) = cwd {
+        cmd.current_dir(dir);
+    }
+
+    let key_var = agent.backend().api_key_var();
+    if let Ok(key) = std::env::var(key_var) {
+        cmd.env(key_var, key);
+    }
+    cmd.envs(args.agent_env(role));
+
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    cmd.kill_on_drop(true);
+    limits::AgentLimits::from_args(args).apply(&mut cmd);
+
+    let prompt_preview: String = prompt.chars().take(80).collect();
+    log_line(
+        tag,
+        &format!(
+            "prompt: {}{}",
+            prompt_preview,
+            if prompt.chars().count() > 80 { "..." } else { "" }
+        ),
+    );
+
+    let mut child = cmd.spawn().with_context(|| format!("failed to spawn {}", args.binary_for(role)))?;
+    let started = tokio::time::Instant::now();
+    let mut last_output = started;
+    let stdout = child.stdout.take().with_context(|| format!("missing {} stdout", tag))?;
+    let stderr = child.stderr.take().with_context(|| format!("missing {} stderr", tag))?;
+    let mut stdout_reader = LineReader::new(stdout, err_tag, args.max_line_length);
+    let mut stderr_reader = LineReader::new(stderr, err_tag, args.max_line_length);
+
+    let mut stderr_lines = Vec::new();
+    // Non-JSON stdout, kept for the error if the agent exits non-zero (claude prints some fatal errors there)
+    let mut unparsed_lines = Vec::new();
+    let mut preamble = PreambleFilter::new(args.skip_preamble_lines);
+    let mut stdout_done = false;
+    let mut stderr_done = false;
+    let mut child_status = None;
+
+    loop {
+        tokio::select! {
+            biased;
+
+            _ = tokio::signal::ctrl_c() => {
+                kill_child(&mut child, tag).await;
+                anyhow::bail!("interrupted by user");
+            }
+
+            err = deadline_passed(out.deadline()) => {
+                kill_child(&mut child, tag).await;
+                return Err(err);
+            }
+
+            // Buffered output is abandoned, not drained: the turn has failed
+            err = turn_timed_out(started, args.turn_timeout_secs, tag) => {
+                kill_child(&mut child, tag).await;
+                return Err(err);
+            }
+
+            err = idle_timed_out(last_output, args.idle_timeout_secs, tag) => {
+                kill_child(&mut child, tag).await;
+                return Err(err);
+            }
+
+            status = child.wait(), if child_status.is_none() => {
+                child_status = Some(status.with_context(|| format!("failed to wait for {}", name))?);
+                // Process exited - break out and drain remaining buffered lines
+                break;
+            }
+
+            line = stdout_reader.next_line(), if !stdout_done => {
+                match line {
+                    Ok(Some(line)) => {
+                        last_output = tokio::time::Instant::now();
+                        let parsed = agent.parse_line(&line, out);
+                        if preamble.should_skip(parsed) {
+                            if args.verbose > 0 {
+                                log_line(tag, &format!("skipping preamble line: {}", truncate_line(&line, 100)));
+                            }
+                        } else if !parsed {
+                            log_line(err_tag, &format!("failed to parse stdout line: {}", truncate_line(&line, 100)));
+                            unparsed_lines.push(line);
+                        }
+                    }
+                    Ok(None) => stdout_done = true,
+                    Err(e) => {
+                        log_line(err_tag, &format!("stdout read error: {}", e));
+                        stdout_done = true;
+                    }
+                }
+            }
+
+            line = stderr_reader.next_line(), if !stderr_done => {
+                match line {
+                    Ok(Some(line)) => {
+                        stderr_lines.push(line);
+                    }
+                    Ok(None) => stderr_done = true,
+                    Err(e) => {
+                        log_line(err_tag, &format!("stderr read error: {}", e));
+                        stderr_done = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // Drain any remaining lines from stdout/stderr after process exits
+    while let Ok(Some(line)) = stdout_reader.next_line().await {
+        let parsed = agent.parse_line(&line, out);
+        if !preamble.should_skip(parsed) && !parsed {
+            log_line(err_tag, &format!("failed to parse stdout line during drain: {}", truncate_line(&line, 100)));
+            unparsed_lines.push(line);
+        }
+    }
+    while let Ok(Some(line)) = stderr_reader.next_line().await {
+        stderr_lines.push(line);
+    }
+
+    let status = child_status.expect("child_status should be set");
+
+    if !status.success() {
+        if !stderr_lines.is_empty() {
+            log_line(err_tag, "stderr output:");
+            for line in &stderr_lines {
+                log_line(err_tag, line);
+            }
+        }
+        return agent.finish(Some(AgentExit::new(tag, status, &stderr_lines).with_unparsed_stdout(&unparsed_lines)));
+    }
+
+    agent.finish(None)
+}
+
+/// `claude -p` with stream-json output
+struct ClaudeAgent {
+    role: Role,
+    access: DriverAccess,
+    select: DriverOutputSelect,
+    /// Resumed by id on a continuation; `--continue` (the most recent session) without one
+    session_id: Option<String>,
+    result: ClaudeResult,
+}
+
+impl ClaudeAgent {
+    fn new(role: Role, access: DriverAccess, select: DriverOutputSelect, session_id: Option<String>) -> Self {
+        ClaudeAgent { role, access, select, session_id, result: ClaudeResult::default() }
+    }
+}
+
+impl Agent for ClaudeAgent {
+    fn backend(&self) -> Backend {
+        Backend::Claude
+    }
+
+    fn add_args(&self, cmd: &mut Command, args: &Args, prompt: &str, is_continuation: bool) {
+        cmd.arg("-p");
+
+        // These take variadic values in claude, so keep them ahead of other options and the prompt
+        if self.role == Role::Driver && !args.driver_allowed_tools.is_empty() {
+            cmd.arg("--allowedTools").arg(args.driver_allowed_tools.join(","));
+        }
+        if self.role == Role::Driver && !args.driver_disallowed_tools.is_empty() {
+            cmd.arg("--disallowedTools").arg(args.driver_disallowed_tools.join(","));
+        }
+        cmd.arg("--verbose");
+        cmd.arg("--output-format").arg("stream-json");
+        match self.access {
+            DriverAccess::Edit => {
+                cmd.arg("--dangerously-skip-permissions");
+                cmd.arg("--permission-mode").arg("acceptEdits");
+            }
+            DriverAccess::ReadOnly => {
+                cmd.arg("--permission-mode").arg("plan");
+            }
+        }
+
+        if args.isolate_agent_context {
+            // Only user-level settings: skips the project's CLAUDE.md and .claude/ settings
+            cmd.arg("--setting-sources").arg("user");
+        }
+
+        if is_continuation {
+            match self.session_id {
+                Some(ref id) => cmd.arg("--resume").arg(id),
+                None => cmd.arg("--continue"),
+            };
+        }
+
+        cmd.arg(prompt);
+    }
+
+    fn resume(&mut self, id: String) {
+        self.session_id = Some(id);
+    }
+
+    fn parse_line(&mut self, line: &str, out: &Output) -> bool {
+        process_claude_line(line, &mut self.result, out, self.role)
+    }
+
+    fn finish(self: Box<Self>, failure: Option<AgentExit>) -> Result<AgentReply> {
+        if let Some(exit) = failure {
+            return Err(exit.into());
+        }
+        Ok(AgentReply {
+            text: self.result.select(self.select),
+            used_tools: self.result.tool_uses > 0,
+            session_id: self.result.session_id,
+            notes: Vec::new(),
+        })
+    }
+}
+
+/// `codex exec --json`
+struct CodexAgent {
+    role: Role,
+    /// Applies to a fresh session only; a resumed one keeps its own
+    sandbox: NavigatorSandbox,
+    forward: CodexForwarding,
+    /// Resumed by id on a continuation; `resume --last` without one
+    session_id: Option<String>,
+    result: CodexResult,
+}
+
+impl CodexAgent {
+    fn new(role: Role, sandbox: NavigatorSandbox, forward: CodexForwarding, session_id: Option<String>) -> Self {
+        CodexAgent { role, sandbox, forward, session_id, result: CodexResult::default() }
+    }
+}
+
+impl Agent for CodexAgent {
+    fn backend(&self) -> Backend {
+        Backend::Codex
+    }
+
+    fn add_args(&self, cmd: &mut Command, args: &Args, prompt: &str, is_continuation: bool) {
+        cmd.arg("exec");
+
+        cmd.arg("--skip-git-repo-check");
+
+        if args.isolate_agent_context {
+            // A zero byte budget for project docs stops codex from reading AGENTS.md
+            cmd.arg("-c").arg("project_doc_max_bytes=0");
+        }
+
+        if is_continuation {
+            cmd.arg("resume");
+            match self.session_id {
+                Some(ref id) => cmd.arg(id),
+                None => cmd.arg("--last"),
+            };
+        } else {
+            cmd.arg("--sandbox").arg(self.sandbox.codex_arg());
+        }
+        cmd.arg("--json");
+        cmd.arg(prompt);
+    }
+
+    fn resume(&mut self, id: String) {
+        self.session_id = Some(id);
+    }
+
+    fn parse_line(&mut self, line: &str, out: &Output) -> bool {
+        process_codex_line(line, &mut self.result, out, self.role, self.forward)
+    }
+
+    fn finish(self: Box<Self>, failure: Option<AgentExit>) -> Result<AgentReply> {
+        let tag = self.role.name();
+        if let Some(exit) = failure {
+            // A reviewer that over-reaches its read-only role shouldn't sink the relay
+            if self.result.collected.is_empty() || !is_sandbox_denied_write(&exit.stderr_tail) {
+                return Err(exit.into());
+            }
+            log_line("system", &format!("{} attempted a write, blocked by sandbox; using its reply", tag));
+        } else if !self.result.turn_completed {
+            log_line(tag, "warning: codex exited without reporting turn.completed; its reply may be cut short");
+        }
+        Ok(AgentReply {
+            text: self.result.collected.join("\n"),
+            used_tools: self.result.commands > 0,
+            session_id: self.result.thread_id,
+            notes: self.result.notes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn args_of(agent: &dyn Agent, args: &Args, is_continuation: bool) -> Vec<String> {
+        let mut cmd = Command::new(agent.backend().name());
+        agent.add_args(&mut cmd, args, "the prompt", is_continuation);
+        cmd.as_std().get_args().map(|a| a.to_string_lossy().into_owned()).collect()
+    }
+
+    // for_seat() tests
+    #[test]
+    fn test_for_seat_builds_each_backends_command() {
+        let args = Args::parse_from(["leonard", "--driver-disallowed-tools", "WebFetch"]);
+        let driver = for_seat(&args, Role::Driver, DriverAccess::Edit);
+        assert_eq!(driver.backend(), Backend::Claude);
+        let argv = args_of(driver.as_ref(), &args, true);
+        assert_eq!(&argv[..3], ["-p", "--disallowedTools", "WebFetch"]);
+        assert!(argv.contains(&"acceptEdits".to_string()));
+        assert_eq!(&argv[argv.len() - 2..], ["--continue", "the prompt"]);
+
+        let navigator = for_seat(&args, Role::Navigator, DriverAccess::ReadOnly);
+        assert_eq!(args_of(navigator.as_ref(), &args, false), ["exec", "--skip-git-repo-check", "--sandbox", "read-only", "--json", "the prompt"]);
+        assert_eq!(args_of(navigator.as_ref(), &args, true), ["exec", "--skip-git-repo-check", "resume", "--last", "--json", "the prompt"]);
+    }
+
+    #[test]
+    fn test_for_seat_reversed() {
+        let args = Args::parse_from(["leonard", "--reverse", "--driver-disallowed-tools", "WebFetch"]);
+        let driver = for_seat(&args, Role::Driver, DriverAccess::Edit);
+        assert_eq!(driver.backend(), Backend::Codex);
+        assert!(args_of(driver.as_ref(), &args, false).contains(&"workspace-write".to_string()));
+
+        let navigator = for_seat(&args, Role::Navigator, DriverAccess::ReadOnly);
+        let argv = args_of(navigator.as_ref(), &args, false);
+        assert!(argv.contains(&"plan".to_string()));
+        assert!(!argv.contains(&"--disallowedTools".to_string()));
+    }
+
+    // Agent::finish() tests
+    #[test]
+    #[cfg(unix)]
+    fn test_codex_finish_tolerates_sandbox_denial_only_with_a_reply() {
+        use std::os::unix::process::ExitStatusExt;
+        let denied = || {
+            let status = std::process::ExitStatus::from_raw(1 << 8);
+            Some(AgentExit::new("navigator", status, &["error: Read-only file system (os error 30)".to_string()]))
+        };
+
+        let agent = Box::new(CodexAgent::new(Role::Navigator, NavigatorSandbox::ReadOnly, CodexForwarding::default(), None));
+        assert!(agent.finish(denied()).is_err());
+
+        let mut agent = Box::new(CodexAgent::new(Role::Navigator, NavigatorSandbox::ReadOnly, CodexForwarding::default(), None));
+        agent.result.collected.push("rename the helper".to_string());
+        assert_eq!(agent.finish(denied()).unwrap().text, "rename the helper");
+    }
+
+    #[test]
+    fn test_resume_continues_that_session() {
+        let args = Args::parse_from(["leonard"]);
+        let mut navigator = for_seat(&args, Role::Navigator, DriverAccess::ReadOnly);
+        navigator.resume("t-1".to_string());
+        assert_eq!(args_of(navigator.as_ref(), &args, true)[2..5], ["resume", "t-1", "--json"]);
+
+        let args = Args::parse_from(["leonard", "--reverse"]);
+        let mut navigator = for_seat(&args, Role::Navigator, DriverAccess::ReadOnly);
+        navigator.resume("s-1".to_string());
+        let argv = args_of(navigator.as_ref(), &args, true);
+        assert_eq!(&argv[argv.len() - 3..], ["--resume", "s-1", "the prompt"]);
+    }
+}
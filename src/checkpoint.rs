@@ -0,0 +1,104 @@
+//! `--checkpoint-dir`: the relay loop's own state between turns, for `--resume-checkpoint`
+//!
+//! The agents keep their conversations in their own sessions; what they don't know is
+//! where Leonard's loop was. One `turn-N.json` is written at the start of each turn
+//! (temp file, then rename), and a resumed run picks up from the highest N.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The loop state at the start of a turn: the navigator is about to review `driver_output`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub turn: usize,
+    pub driver_output: String,
+    /// The navigators' combined feedback from the turn before, empty before the first review
+    pub navigator_output: String,
+    /// Whether each agent has a session to continue
+    pub driver_started: bool,
+    pub navigator_started: bool,
+    /// Each navigator's session id (--navigators above 1)
+    pub navigator_sessions: Vec<Option<String>>,
+    pub driver_used_tools: bool,
+    pub skipped_review: bool,
+    pub done_disagreements: usize,
+}
+
+impl Checkpoint {
+    /// Write this checkpoint into `dir`, creating it if needed
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        let path = dir.join(format!("turn-{}.json", self.turn));
+        let tmp = dir.join(format!("turn-{}.json.tmp", self.turn));
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(&tmp, json + "\n").with_context(|| format!("failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// The checkpoint with the highest turn in `dir`, and its path
+    pub fn load_latest(dir: &Path) -> Result<(Self, PathBuf)> {
+        let entries = std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+        let latest = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Some((checkpoint_turn(&entry.file_name().to_string_lossy())?, entry.path())))
+            .max_by_key(|(turn, _)| *turn);
+        let Some((_, path)) = latest else {
+            anyhow::bail!("no checkpoint in {}", dir.display());
+        };
+        let content = std::fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let checkpoint =
+            serde_json::from_str(&content).with_context(|| format!("failed to parse checkpoint {}", path.display()))?;
+        Ok((checkpoint, path))
+    }
+}
+
+/// The turn in a checkpoint file name, `turn-N.json`
+fn checkpoint_turn(name: &str) -> Option<usize> {
+    name.strip_prefix("turn-")?.strip_suffix(".json")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(turn: usize) -> Checkpoint {
+        Checkpoint {
+            turn,
+            driver_output: format!("driver reply {}", turn),
+            navigator_output: "add tests".to_string(),
+            driver_started: true,
+            navigator_started: turn > 0,
+            navigator_sessions: vec![None, Some("abc".to_string())],
+            driver_used_tools: true,
+            skipped_review: false,
+            done_disagreements: 1,
+        }
+    }
+
+    #[test]
+    fn test_load_latest_picks_highest_turn() {
+        let dir = std::env::temp_dir().join(format!("leonard-checkpoint-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(Checkpoint::load_latest(&dir).is_err());
+
+        for turn in [0, 10, 2] {
+            checkpoint(turn).save(&dir).unwrap();
+        }
+        std::fs::write(dir.join("turn-99.json.tmp"), "partial").unwrap();
+        let (latest, path) = Checkpoint::load_latest(&dir).unwrap();
+        assert_eq!(latest, checkpoint(10));
+        assert_eq!(path, dir.join("turn-10.json"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    // checkpoint_turn() tests
+
+    #[test]
+    fn test_checkpoint_turn() {
+        assert_eq!(checkpoint_turn("turn-7.json"), Some(7));
+        assert_eq!(checkpoint_turn("turn-7.json.tmp"), None);
+        assert_eq!(checkpoint_turn("notes.json"), None);
+    }
+}
@@ -0,0 +1,278 @@
+//! `leonard.toml`: file-based defaults and named presets for `Args`
+//!
+//! Precedence, highest first: CLI flags > the selected `[preset.<name>]` table >
+//! top-level config keys > built-in defaults. Keys use the long flag names, with
+//! either dashes or underscores (`max-turns = 5` or `max_turns = 5`). Keys that match
+//! no flag are warned about and skipped.
+
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches};
+use std::ffi::OsString;
+use std::path::Path;
+
+use crate::{log_line, Args, EXIT_ERROR};
+
+pub const CONFIG_FILE_NAME: &str = "leonard.toml";
+
+/// Table holding named presets, e.g. `[preset.security-audit]`
+const PRESET_TABLE: &str = "preset";
+
+/// Parse the command line, then fill in anything it didn't set from `leonard.toml`
+pub fn load_args() -> Result<Args> {
+    let cli: Vec<OsString> = std::env::args_os().collect();
+    let matches = Args::command().try_get_matches_from(&cli).unwrap_or_else(|e| exit_on(e));
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| exit_on(e));
+
+    // An explicit --config must exist; the default leonard.toml is optional
+    let path = match args.config {
+        Some(ref path) => {
+            if !path.exists() {
+                anyhow::bail!("config file {} not found", path.display());
+            }
+            path.clone()
+        }
+        None => args.cwd.clone().unwrap_or_else(|| ".".into()).join(CONFIG_FILE_NAME),
+    };
+
+    match read_config(&path)? {
+        Some(table) => merge_config(&cli, args, &matches, table, &path),
+        None => {
+            if let Some(ref preset) = args.preset {
+                anyhow::bail!("--preset {} given, but no {} was found", preset, path.display());
+            }
+            Ok(args)
+        }
+    }
+}
+
+/// Print a command-line error (or --help/--version) and exit; a usage error exits with
+/// `EXIT_ERROR`, since clap's usual 2 means a relay hit --max-turns
+fn exit_on(e: clap::Error) -> ! {
+    let _ = e.print();
+    std::process::exit(if e.use_stderr() { EXIT_ERROR.into() } else { 0 });
+}
+
+/// Read and parse a config file; a missing file is not an error
+fn read_config(path: &Path) -> Result<Option<toml::Table>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let table = content
+        .parse::<toml::Table>()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    Ok(Some(table))
+}
+
+/// Layer the base config and the selected preset (read from `path`) under the parsed CLI args
+/// (`cli` is the command line they were parsed from)
+fn merge_config(cli: &[OsString], mut args: Args, matches: &ArgMatches, mut table: toml::Table, path: &Path) -> Result<Args> {
+    let presets = match table.remove(PRESET_TABLE) {
+        None => toml::Table::new(),
+        Some(toml::Value::Table(presets)) => presets,
+        Some(_) => anyhow::bail!("`{}` in {} must be a table of presets", PRESET_TABLE, path.display()),
+    };
+
+    if let Some(ref name) = args.preset {
+        match presets.get(name) {
+            Some(toml::Value::Table(preset)) => {
+                for (key, value) in preset {
+                    table.insert(key.clone(), value.clone());
+                }
+            }
+            Some(_) => anyhow::bail!("preset '{}' must be a table", name),
+            None => {
+                let mut available: Vec<&str> = presets.keys().map(String::as_str).collect();
+                available.sort_unstable();
+                anyhow::bail!(
+                    "unknown preset '{}' (available: {})",
+                    name,
+                    if available.is_empty() { "none".to_string() } else { available.join(", ") }
+                );
+            }
+        }
+    }
+
+    // Subcommands aren't configurable; carry them across the round-trip
+    let command = args.command.take();
+
+    let mut value = serde_json::to_value(&args).context("failed to serialize arguments")?;
+    let fields = value.as_object_mut().context("arguments did not serialize to an object")?;
+    let mut from_config = Vec::new();
+    for (key, setting) in table {
+        let field = key.replace('-', "_");
+        if !fields.contains_key(&field) {
+            log_line("system", &format!("warning: ignoring unknown key `{}` in {}", key, path.display()));
+            continue;
+        }
+        if matches.value_source(&field) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let setting = serde_json::to_value(setting)
+            .with_context(|| format!("invalid value for `{}` in {}", key, path.display()))?;
+        from_config.push((field.clone(), setting.clone()));
+        fields.insert(field, setting);
+    }
+    validate_with_clap(cli, &from_config)
+        .with_context(|| format!("invalid setting in {}", path.display()))?;
+
+    let mut merged: Args = serde_json::from_value(value)
+        .with_context(|| format!("invalid setting in {}", path.display()))?;
+    merged.command = command;
+    Ok(merged)
+}
+
+/// Re-run clap over the command line with the config's settings spelled as flags, so they
+/// get the same value parsers, ranges, conflicts and requirements as the command line.
+/// The settings are still applied through serde; this only rejects what clap would
+fn validate_with_clap(cli: &[OsString], settings: &[(String, serde_json::Value)]) -> Result<()> {
+    let command = Args::command();
+    let mut argv: Vec<OsString> = cli.iter().take(1).cloned().collect();
+    for (field, setting) in settings {
+        let Some(arg) = command.get_arguments().find(|arg| arg.get_id() == field.as_str()) else { continue };
+        let Some(long) = arg.get_long() else { continue };
+        match arg.get_action() {
+            // `false` can't be written as a flag, and can't conflict with anything
+            ArgAction::SetTrue => {
+                if setting.as_bool() == Some(true) {
+                    argv.push(format!("--{}", long).into());
+                }
+            }
+            ArgAction::Count => {
+                let count = setting.as_u64().unwrap_or_default();
+                argv.extend((0..count).map(|_| OsString::from(format!("--{}", long))));
+            }
+            _ => {
+                let values = match setting {
+                    serde_json::Value::Array(values) => values.clone(),
+                    value => vec![value.clone()],
+                };
+                for value in values {
+                    let value = match value {
+                        serde_json::Value::Null => continue,
+                        serde_json::Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    argv.push(format!("--{}={}", long, value).into());
+                }
+            }
+        }
+    }
+    // Subcommands and their flags stay after the top-level flags
+    argv.extend(cli.iter().skip(1).cloned());
+    match command.try_get_matches_from(argv) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let message = e.to_string();
+            let first = message.lines().next().unwrap_or_default();
+            anyhow::bail!("{}", first.strip_prefix("error: ").unwrap_or(first))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(cli: &[&str], config: &str) -> Result<Args> {
+        let matches = Args::command().try_get_matches_from(cli)?;
+        let args = Args::from_arg_matches(&matches)?;
+        let cli: Vec<OsString> = cli.iter().map(OsString::from).collect();
+        merge_config(&cli, args, &matches, config.parse::<toml::Table>()?, Path::new(CONFIG_FILE_NAME))
+    }
+
+    #[test]
+    fn test_config_fills_unset_fields() {
+        let args = merge(&["leonard"], "max-turns = 3\nmax_forward_bytes = 500\ntask = \"from config\"").unwrap();
+        assert_eq!(args.max_turns, 3);
+        assert_eq!(args.max_forward_bytes, 500);
+        assert_eq!(args.task.as_deref(), Some("from config"));
+    }
+
+    #[test]
+    fn test_cli_overrides_config() {
+        let args = merge(&["leonard", "--max-turns", "7"], "max-turns = 3").unwrap();
+        assert_eq!(args.max_turns, 7);
+    }
+
+    #[test]
+    fn test_config_can_disable_default_true_flag() {
+        let args = merge(&["leonard"], "strip-ansi = false").unwrap();
+        assert!(!args.strip_ansi);
+    }
+
+    #[test]
+    fn test_preset_overrides_base_but_not_cli() {
+        let config = r#"
+max-turns = 3
+max-forward-bytes = 100
+
+[preset.security-audit]
+max-turns = 12
+review-only = true
+"#;
+        let args = merge(&["leonard", "--preset", "security-audit"], config).unwrap();
+        assert_eq!(args.max_turns, 12);
+        assert!(args.review_only);
+        assert_eq!(args.max_forward_bytes, 100);
+
+        let args = merge(&["leonard", "--preset", "security-audit", "--max-turns", "2"], config).unwrap();
+        assert_eq!(args.max_turns, 2);
+    }
+
+    #[test]
+    fn test_presets_ignored_without_flag() {
+        let args = merge(&["leonard"], "[preset.deep]\nmax-turns = 40").unwrap();
+        assert_eq!(args.max_turns, 10);
+    }
+
+    #[test]
+    fn test_unknown_preset_lists_available() {
+        let config = "[preset.quick-review]\nmax-turns = 2\n\n[preset.deep-build]\nmax-turns = 30";
+        let err = merge(&["leonard", "--preset", "nope"], config).unwrap_err().to_string();
+        assert!(err.contains("unknown preset 'nope'"));
+        assert!(err.contains("deep-build, quick-review"));
+    }
+
+    #[test]
+    fn test_unknown_keys_are_skipped() {
+        let args = merge(&["leonard"], "max-turns = 4\nmax-turnz = 9").unwrap();
+        assert_eq!(args.max_turns, 4);
+    }
+
+    #[test]
+    fn test_invalid_value_is_an_error() {
+        assert!(merge(&["leonard"], "max-turns = \"lots\"").is_err());
+        assert!(merge(&["leonard"], "done-regex = \"(unclosed\"").is_err());
+        assert!(merge(&["leonard"], "done-regex = \"LGTM\"").unwrap().done_regex.is_some());
+    }
+
+    #[test]
+    fn test_config_values_get_the_cli_checks() {
+        let err = format!("{:#}", merge(&["leonard"], "deadline = 0").unwrap_err());
+        assert!(err.contains("invalid setting in leonard.toml"), "{}", err);
+        assert!(err.contains("--deadline"), "{}", err);
+        assert!(merge(&["leonard"], "agent-nice = 50").is_err());
+        assert!(merge(&["leonard"], "meta = [\"no-equals-sign\"]").is_err());
+        assert!(merge(&["leonard"], "meta = [\"team=core\"]").is_ok());
+    }
+
+    #[test]
+    fn test_config_conflicts_and_requirements_are_rejected() {
+        let err = format!("{:#}", merge(&["leonard"], "navigator-only = true\ndriver-only = true").unwrap_err());
+        assert!(err.contains("cannot be used with"), "{}", err);
+        // One side from the command line, the other from the file
+        assert!(merge(&["leonard", "--review-only"], "navigator-sandbox = \"workspace-write\"").is_err());
+        assert!(merge(&["leonard"], "resume-checkpoint = true").is_err());
+        // Settings that are off can't conflict
+        assert!(merge(&["leonard", "--driver-only"], "navigator-only = false").is_ok());
+    }
+
+    #[test]
+    fn test_enum_values_use_cli_spelling() {
+        let args = merge(&["leonard"], "on-empty-navigator = \"stop\"").unwrap();
+        assert_eq!(args.on_empty_navigator, crate::EmptyNavigatorAction::Stop);
+    }
+}
@@ -0,0 +1,230 @@
+//! `leonard doctor`: an extended, human-readable version of the preflight checks
+
+use anyhow::Result;
+use colored::Colorize;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::agent::Backend;
+use crate::output::Role;
+use crate::{api_key_problem, maybe_color, validate_working_directory, Args};
+
+/// Flags Leonard passes to `claude`; all must appear in `claude --help`
+const CLAUDE_REQUIRED_FLAGS: &[&str] = &["--output-format", "--permission-mode", "--continue", "--verbose"];
+
+/// Flags Leonard passes to `codex exec`; all must appear in `codex exec --help`
+const CODEX_REQUIRED_FLAGS: &[&str] = &["--json", "--sandbox", "--skip-git-repo-check"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result of a single diagnostic check
+#[derive(Debug)]
+struct Check {
+    name: &'static str,
+    status: CheckStatus,
+    detail: String,
+    hint: Option<&'static str>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Check { name, status: CheckStatus::Pass, detail: detail.into(), hint: None }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: &'static str) -> Self {
+        Check { name, status: CheckStatus::Warn, detail: detail.into(), hint: Some(hint) }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: &'static str) -> Self {
+        Check { name, status: CheckStatus::Fail, detail: detail.into(), hint: Some(hint) }
+    }
+
+    fn print(&self, colors: bool) {
+        let label = match self.status {
+            CheckStatus::Pass => maybe_color(colors, "[PASS]", |s| s.green().bold()),
+            CheckStatus::Warn => maybe_color(colors, "[WARN]", |s| s.yellow().bold()),
+            CheckStatus::Fail => maybe_color(colors, "[FAIL]", |s| s.red().bold()),
+        };
+        println!("{} {}: {}", label, self.name, self.detail);
+        if let Some(hint) = self.hint {
+            println!("       {}", maybe_color(colors, format!("hint: {}", hint), |s| s.dimmed()));
+        }
+    }
+}
+
+/// Run `binary <args>` and capture its combined stdout/stderr
+async fn capture_output(binary: &str, args: &[&str]) -> std::io::Result<String> {
+    let output = Command::new(binary)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(text)
+}
+
+/// Return the required flags that don't appear anywhere in `help_text`
+fn missing_flags<'a>(help_text: &str, required: &[&'a str]) -> Vec<&'a str> {
+    required
+        .iter()
+        .copied()
+        .filter(|flag| !help_text.contains(flag))
+        .collect()
+}
+
+async fn check_binary(name: &'static str, binary: &str, install_hint: &'static str) -> Check {
+    match capture_output(binary, &["--version"]).await {
+        Ok(text) => {
+            let version = text.lines().next().unwrap_or("").trim();
+            if version.is_empty() {
+                Check::pass(name, format!("{} found", binary))
+            } else {
+                Check::pass(name, format!("{} ({})", binary, version))
+            }
+        }
+        Err(e) => Check::fail(name, format!("{} not runnable: {}", binary, e), install_hint),
+    }
+}
+
+async fn check_capabilities(
+    name: &'static str,
+    binary: &str,
+    help_args: &[&str],
+    required: &[&str],
+    upgrade_hint: &'static str,
+) -> Check {
+    match capture_output(binary, help_args).await {
+        Ok(help) => {
+            let missing = missing_flags(&help, required);
+            if missing.is_empty() {
+                Check::pass(name, format!("supports {}", required.join(", ")))
+            } else {
+                Check::fail(name, format!("missing {}", missing.join(", ")), upgrade_hint)
+            }
+        }
+        Err(e) => Check::fail(name, format!("could not run {} {}: {}", binary, help_args.join(" "), e), upgrade_hint),
+    }
+}
+
+/// The binary, flag, and API key checks for the agent in one seat
+async fn check_agent(args: &Args, role: Role) -> Vec<Check> {
+    let (binary_name, flags_name, key_name) = match role {
+        Role::Driver => ("driver binary", "driver flags", "driver API key"),
+        Role::Navigator => ("navigator binary", "navigator flags", "navigator API key"),
+    };
+    let backend = args.backend(role);
+    let bin = args.binary_for(role);
+    let (help_args, required, upgrade_hint, key_hint): (&[&str], _, _, _) = match backend {
+        Backend::Claude => (
+            &["--help"],
+            CLAUDE_REQUIRED_FLAGS,
+            "upgrade the Claude Code CLI to a version with stream-json output",
+            "export ANTHROPIC_API_KEY, or log in with `claude` first",
+        ),
+        Backend::Codex => (
+            &["exec", "--help"],
+            CODEX_REQUIRED_FLAGS,
+            "upgrade the Codex CLI to a version with `exec --json`",
+            "export OPENAI_API_KEY, or log in with `codex login` first",
+        ),
+    };
+    vec![
+        check_binary(binary_name, &bin, backend.install_hint()).await,
+        check_capabilities(flags_name, &bin, help_args, required, upgrade_hint).await,
+        check_api_key(key_name, backend.api_key_var(), key_hint),
+    ]
+}
+
+fn check_api_key(name: &'static str, key_name: &str, hint: &'static str) -> Check {
+    match api_key_problem(key_name) {
+        None => Check::pass(name, format!("{} is set", key_name)),
+        Some(problem) => Check::warn(name, format!("{} {}", key_name, problem), hint),
+    }
+}
+
+fn check_working_directory(dir: &Path) -> Check {
+    const NAME: &str = "working directory";
+    if let Err(e) = validate_working_directory(dir) {
+        return Check::fail(NAME, e.to_string(), "pass an existing directory with --cwd");
+    }
+    match std::fs::read_dir(dir) {
+        Ok(_) => Check::pass(NAME, format!("{} is accessible", dir.display())),
+        Err(e) => Check::fail(NAME, format!("{} is not readable: {}", dir.display(), e), "check the directory permissions"),
+    }
+}
+
+fn check_leonard_md(dir: &Path) -> Check {
+    const NAME: &str = "leonard.md";
+    let path = dir.join("leonard.md");
+    match std::fs::read_to_string(&path) {
+        Ok(content) if !content.trim().is_empty() => {
+            Check::pass(NAME, format!("{} ({} chars)", path.display(), content.chars().count()))
+        }
+        Ok(_) => Check::warn(NAME, format!("{} is empty", path.display()), "add project context, or pass --task on every run"),
+        Err(_) => Check::warn(NAME, "not found", "optional; without it every run needs --task"),
+    }
+}
+
+/// Run every diagnostic, print a pass/warn/fail report, and fail if any hard check failed
+pub async fn run_doctor(args: &Args) -> Result<()> {
+    let dir = args.cwd.clone().unwrap_or_else(|| ".".into());
+    let mut checks = check_agent(args, Role::Driver).await;
+    checks.extend(check_agent(args, Role::Navigator).await);
+    checks.push(check_working_directory(&dir));
+    checks.push(check_leonard_md(&dir));
+
+    let colors = args.colors();
+    for check in &checks {
+        check.print(colors);
+    }
+
+    let failed = checks.iter().filter(|c| c.status == CheckStatus::Fail).count();
+    let warned = checks.iter().filter(|c| c.status == CheckStatus::Warn).count();
+    println!();
+    println!("{} check(s): {} failed, {} warning(s)", checks.len(), failed, warned);
+
+    if failed > 0 {
+        anyhow::bail!("doctor found {} failing check(s)", failed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_flags_none_missing() {
+        let help = "Usage: claude [options]\n  --output-format <format>\n  --permission-mode <mode>\n  --continue\n  --verbose";
+        assert!(missing_flags(help, CLAUDE_REQUIRED_FLAGS).is_empty());
+    }
+
+    #[test]
+    fn test_missing_flags_reports_each_missing_flag() {
+        let help = "Usage: codex exec [OPTIONS]\n  --json";
+        assert_eq!(
+            missing_flags(help, CODEX_REQUIRED_FLAGS),
+            vec!["--sandbox", "--skip-git-repo-check"]
+        );
+    }
+
+    #[test]
+    fn test_check_working_directory_missing_fails() {
+        let check = check_working_directory(Path::new("/definitely/not/a/real/dir"));
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.hint.is_some());
+    }
+
+    #[test]
+    fn test_check_working_directory_existing_passes() {
+        let check = check_working_directory(&std::env::temp_dir());
+        assert_eq!(check.status, CheckStatus::Pass);
+    }
+}
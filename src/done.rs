@@ -0,0 +1,143 @@
+//! Deciding whether an agent's reply signals that the task is done
+//!
+//! Every path that asks "is this reply ALL_DONE?" goes through here, so the relay and
+//! `leonard prompts` always agree (the gh-comment verdict is the run's finish reason). Matching, with
+//! `tokens` being the --done-token list (ALL_DONE by default):
+//!
+//! - **Whole reply** (`agent_signaled_done`): the trimmed reply equals a token,
+//!   ignoring case. This alone ends the relay by default.
+//! - **Last line** (`done_signal` with --done-rationale): the reply's final line is a
+//!   token, and the lines before it are the agent's rationale.
+//! - **Any line** (`mentions_all_done`): some line is a token. This never ends the relay
+//!   by itself. It detects a navigator that approved while the driver carried on
+//!   (--max-done-disagreements).
+//! - **Substring**: a token inside a sentence ("ALL_DONE, ship it") never counts,
+//!   since "not ALL_DONE yet" would too. Use --done-regex to opt into looser matching;
+//!   it replaces the token checks in `done_signal`.
+
+use crate::Args;
+
+/// Whether an agent's whole reply is one of the completion tokens (ALL_DONE by default)
+pub fn agent_signaled_done(output: &str, tokens: &[&str]) -> bool {
+    let trimmed = output.trim().to_uppercase();
+    tokens.iter().any(|token| trimmed == token.to_uppercase())
+}
+
+/// If `output` ends the relay, the agent's reason for it: empty for a bare ALL_DONE, or with
+/// --done-rationale, whatever precedes a final ALL_DONE line. With --done-regex, any reply the
+/// pattern matches ends it, and with --done-rationale the whole reply is the reason
+pub fn done_signal<'a>(args: &Args, output: &'a str) -> Option<&'a str> {
+    if let Some(ref pattern) = args.done_regex {
+        return pattern.is_match(output).then(|| if args.done_rationale { output.trim() } else { "" });
+    }
+    let tokens = args.done_tokens();
+    if agent_signaled_done(output, &tokens) {
+        return Some("");
+    }
+    if !args.done_rationale {
+        return None;
+    }
+    let trimmed = output.trim_end();
+    let (rationale, last) = trimmed.rsplit_once('\n')?;
+    agent_signaled_done(last, &tokens).then(|| rationale.trim())
+}
+
+/// Whether any line of an agent's reply is the ALL_DONE marker, even if the reply
+/// as a whole isn't (and so didn't end the relay)
+pub fn mentions_all_done(output: &str, tokens: &[&str]) -> bool {
+    output.lines().any(|line| agent_signaled_done(line, tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_DONE_TOKEN;
+    use clap::Parser;
+
+    const PROSE: &str = "Everything looks good — ALL_DONE, ship it.";
+
+    // agent_signaled_done() tests
+    #[test]
+    fn test_agent_signaled_done_exact() {
+        assert!(agent_signaled_done("ALL_DONE", &[DEFAULT_DONE_TOKEN]));
+    }
+
+    #[test]
+    fn test_agent_signaled_done_lowercase() {
+        assert!(agent_signaled_done("all_done", &[DEFAULT_DONE_TOKEN]));
+    }
+
+    #[test]
+    fn test_agent_signaled_done_mixed_case() {
+        assert!(agent_signaled_done("All_Done", &[DEFAULT_DONE_TOKEN]));
+        assert!(agent_signaled_done("aLL_dONE", &[DEFAULT_DONE_TOKEN]));
+    }
+
+    #[test]
+    fn test_agent_signaled_done_with_whitespace() {
+        assert!(agent_signaled_done("  ALL_DONE  ", &[DEFAULT_DONE_TOKEN]));
+        assert!(agent_signaled_done("\nALL_DONE\n", &[DEFAULT_DONE_TOKEN]));
+        assert!(agent_signaled_done("\t\tALL_DONE\t\t", &[DEFAULT_DONE_TOKEN]));
+    }
+
+    #[test]
+    fn test_agent_signaled_done_false() {
+        assert!(!agent_signaled_done("Not done yet", &[DEFAULT_DONE_TOKEN]));
+        assert!(!agent_signaled_done("ALMOST_DONE", &[DEFAULT_DONE_TOKEN]));
+        assert!(!agent_signaled_done("ALL_DONE but more text", &[DEFAULT_DONE_TOKEN]));
+        assert!(!agent_signaled_done("", &[DEFAULT_DONE_TOKEN]));
+    }
+
+    #[test]
+    fn test_agent_signaled_done_custom_tokens() {
+        let args = Args::parse_from(["leonard", "--done-token", "TASK_COMPLETE, fertig"]);
+        assert_eq!(args.done_tokens(), vec!["TASK_COMPLETE", "fertig"]);
+        assert!(agent_signaled_done(" task_complete\n", &args.done_tokens()));
+        assert!(agent_signaled_done("FERTIG", &args.done_tokens()));
+        assert!(!agent_signaled_done("ALL_DONE", &args.done_tokens()));
+        assert_eq!(Args::parse_from(["leonard", "--done-token", " "]).done_tokens(), vec![DEFAULT_DONE_TOKEN]);
+    }
+
+    // done_signal() tests
+    #[test]
+    fn test_done_signal_whole_reply() {
+        let args = Args::parse_from(["leonard"]);
+        assert_eq!(done_signal(&args, " all_done\n"), Some(""));
+        assert_eq!(done_signal(&args, "Looks good.\nALL_DONE"), None);
+        assert_eq!(done_signal(&args, PROSE), None);
+    }
+
+    #[test]
+    fn test_done_signal_rationale() {
+        let reply = "Tests pass and the edge cases are covered.\n\nALL_DONE\n";
+        let args = Args::parse_from(["leonard", "--done-rationale"]);
+        assert_eq!(done_signal(&args, "ALL_DONE"), Some(""));
+        assert_eq!(done_signal(&args, reply), Some("Tests pass and the edge cases are covered."));
+        assert_eq!(done_signal(&args, "ALL_DONE\nexcept the docs"), None);
+        assert_eq!(done_signal(&args, PROSE), None);
+    }
+
+    #[test]
+    fn test_done_signal_regex() {
+        let args = Args::parse_from(["leonard", "--done-regex", r"(?i)\ball_done\b"]);
+        assert_eq!(done_signal(&args, PROSE), Some(""));
+        assert_eq!(done_signal(&args, "Not ALL_DONE_YET"), None);
+
+        let args = Args::parse_from(["leonard", "--done-regex", "ship it", "--done-rationale"]);
+        assert_eq!(done_signal(&args, PROSE), Some(PROSE));
+        // The regex replaces the token rather than adding to it
+        assert_eq!(done_signal(&args, "ALL_DONE"), None);
+
+        assert!(Args::try_parse_from(["leonard", "--done-regex", "(unclosed"]).is_err());
+    }
+
+    // mentions_all_done() tests
+    #[test]
+    fn test_mentions_all_done() {
+        assert!(mentions_all_done("Looks good to me.\n\nALL_DONE", &[DEFAULT_DONE_TOKEN]));
+        assert!(mentions_all_done("  all_done  ", &[DEFAULT_DONE_TOKEN]));
+        assert!(!mentions_all_done("Not ALL_DONE yet: fix the test", &[DEFAULT_DONE_TOKEN]));
+        assert!(!mentions_all_done(PROSE, &[DEFAULT_DONE_TOKEN]));
+        assert!(!mentions_all_done("", &[DEFAULT_DONE_TOKEN]));
+    }
+}
@@ -0,0 +1,128 @@
+//! `--output gh-comment`: the relay as a GitHub pull request comment body
+//!
+//! Nothing is printed while the relay runs; once it ends, the transcript is rendered
+//! as GitHub-flavored Markdown for `gh pr comment --body-file -`: a summary header,
+//! the navigator's final assessment, and (with --gh-comment-dialogue) every turn in a
+//! collapsible `<details>` section.
+
+use crate::done::agent_signaled_done;
+use crate::markdown::close_open_fence;
+use crate::output::{FinishReason, Role};
+use crate::transcript::TranscriptEntry;
+
+/// Render the comment body for a relay that ended after `turns` turn(s) for `reason`
+pub fn render(
+    task: Option<&str>,
+    entries: &[TranscriptEntry],
+    turns: usize,
+    reason: FinishReason,
+    done_tokens: &[&str],
+    dialogue: bool,
+) -> String {
+    let messages: Vec<(usize, Role, &str)> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            TranscriptEntry::Message { turn, role, text, .. } => Some((*turn, *role, text.as_str())),
+            TranscriptEntry::Error { .. } => None,
+        })
+        .collect();
+    let last_navigator = messages.iter().rev().find(|(_, role, _)| *role == Role::Navigator).map(|m| m.2);
+
+    let mut body = String::from("## Leonard review\n\n");
+    if let Some(task) = task {
+        body.push_str(&format!("**Task:** {}\n\n", task.lines().collect::<Vec<_>>().join(" ")));
+    }
+    // The verdict is why the relay ended, not a re-reading of the last reply
+    let verdict = match (last_navigator, reason) {
+        (_, FinishReason::AllDone) => "✅ signed off as done",
+        (None, _) => "no navigator review",
+        (Some(_), reason) => &format!("⚠️ ended without sign-off (`{}`)", reason.name()),
+    };
+    body.push_str(&format!("**Result:** {} after {} turn(s)\n", verdict, turns));
+
+    if let Some(text) = last_navigator {
+        let assessment = text.lines().filter(|l| !agent_signaled_done(l, done_tokens)).collect::<Vec<_>>().join("\n");
+        let assessment = assessment.trim();
+        body.push_str("\n### Final assessment\n\n");
+        if assessment.is_empty() {
+            body.push_str("_The navigator had no further feedback._\n");
+        } else {
+            body.push_str(&close_open_fence(assessment));
+            body.push('\n');
+        }
+    }
+
+    if dialogue && !messages.is_empty() {
+        body.push_str("\n### Dialogue\n");
+        for (turn, role, text) in &messages {
+            let label = match role {
+                Role::Driver => "Driver",
+                Role::Navigator => "Navigator",
+            };
+            body.push_str(&format!(
+                "\n<details>\n<summary>Turn {}: {}</summary>\n\n{}\n\n</details>\n",
+                turn,
+                label,
+                close_open_fence(text.trim())
+            ));
+        }
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DONE: &[&str] = &["ALL_DONE"];
+
+    fn message(turn: usize, role: Role, text: &str) -> TranscriptEntry {
+        TranscriptEntry::Message { turn, role, timestamp: String::new(), text: text.to_string(), activity: Vec::new() }
+    }
+
+    // render() tests
+    #[test]
+    fn test_render_summary_and_final_assessment() {
+        let entries = vec![
+            message(0, Role::Driver, "added pagination"),
+            message(0, Role::Navigator, "Looks good.\n\n```rust\nlet x = 1;\n```\nALL_DONE"),
+        ];
+        let body = render(Some("Add pagination"), &entries, 1, FinishReason::AllDone, DONE, false);
+        assert!(body.starts_with("## Leonard review\n\n**Task:** Add pagination\n\n"));
+        assert!(body.contains("signed off as done after 1 turn(s)"));
+        assert!(body.contains("### Final assessment\n\nLooks good.\n\n```rust\nlet x = 1;\n```\n"));
+        assert!(!body.contains("<details>"));
+    }
+
+    #[test]
+    fn test_render_dialogue_in_details() {
+        let entries = vec![
+            message(0, Role::Driver, "plan"),
+            message(0, Role::Navigator, "add tests"),
+            message(1, Role::Driver, "done"),
+        ];
+        let body = render(None, &entries, 1, FinishReason::MaxTurns, DONE, true);
+        assert!(body.contains("ended without sign-off (`max_turns`)"));
+        assert_eq!(body.matches("<details>").count(), 3);
+        assert!(body.contains("<summary>Turn 1: Driver</summary>\n\ndone\n\n</details>"));
+    }
+
+    #[test]
+    fn test_render_bare_all_done() {
+        let body = render(None, &[message(0, Role::Navigator, "ALL_DONE")], 0, FinishReason::AllDone, DONE, false);
+        assert!(body.contains("_The navigator had no further feedback._"));
+    }
+
+    #[test]
+    fn test_render_verdict_follows_finish_reason() {
+        // An ALL_DONE line that didn't end the relay isn't a sign-off
+        let entries = vec![message(0, Role::Driver, "plan"), message(0, Role::Navigator, "Looks good\nALL_DONE")];
+        let body = render(None, &entries, 10, FinishReason::MaxTurns, DONE, false);
+        assert!(body.contains("ended without sign-off (`max_turns`) after 10 turn(s)"));
+
+        // A --done-regex match ends the relay without the token
+        let entries = vec![message(0, Role::Driver, "plan"), message(0, Role::Navigator, "LGTM, ship it")];
+        let body = render(None, &entries, 1, FinishReason::AllDone, DONE, false);
+        assert!(body.contains("signed off as done after 1 turn(s)"));
+    }
+}
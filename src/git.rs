@@ -0,0 +1,202 @@
+//! Small helpers for shelling out to `git` in the agents' working directory
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::log_line;
+
+/// Run `git <args>` in `dir` and return its trimmed stdout
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    git_with_env(dir, args, &[])
+}
+
+fn git_with_env(dir: &Path, args: &[&str], env: &[(&str, &str)]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .envs(env.iter().copied())
+        .output()
+        .context("failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Tracked and untracked-but-not-ignored files under `dir`, relative to it
+pub fn listed_files(dir: &Path) -> Result<Vec<String>> {
+    let out = git(dir, &["ls-files", "--cached", "--others", "--exclude-standard"])?;
+    Ok(out.lines().filter(|l| !l.is_empty()).map(str::to_string).collect())
+}
+
+/// A temporary detached `git worktree` checkout, removed again on drop
+pub struct Worktree {
+    repo: PathBuf,
+    path: PathBuf,
+}
+
+impl Worktree {
+    /// Check out `git_ref` from the repository at `repo` into a fresh temp directory
+    pub fn create(repo: &Path, git_ref: &str) -> Result<Self> {
+        git(repo, &["rev-parse", "--git-dir"])
+            .with_context(|| format!("{} is not inside a git repository", repo.display()))?;
+        git(repo, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", git_ref)])
+            .with_context(|| format!("unknown git ref '{}'", git_ref))?;
+
+        let path = std::env::temp_dir().join(format!(
+            "leonard-navigator-{}-{}",
+            std::process::id(),
+            crate::generate_nonce()
+        ));
+        let path_str = path.to_string_lossy().to_string();
+        git(repo, &["worktree", "add", "--detach", &path_str, git_ref])
+            .with_context(|| format!("failed to check out '{}' into a worktree", git_ref))?;
+
+        log_line("system", &format!("checked out {} into {}", git_ref, path.display()));
+        Ok(Worktree { repo: repo.to_path_buf(), path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let path_str = self.path.to_string_lossy().to_string();
+        match git(&self.repo, &["worktree", "remove", "--force", &path_str]) {
+            Ok(_) => log_line("system", &format!("removed worktree {}", self.path.display())),
+            Err(e) => log_line("system", &format!("warning: failed to remove worktree {}: {}", self.path.display(), e)),
+        }
+    }
+}
+
+/// A commit recording the working tree right after a driver turn
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub turn: usize,
+    pub refname: String,
+    pub sha: String,
+}
+
+/// Snapshots the working tree after each driver turn (--git-checkpoint).
+///
+/// Each snapshot is a commit under `refs/leonard/<run>/turn-N`, built with a private
+/// index file, so HEAD, the user's index, and the working tree are never touched.
+pub struct Checkpointer {
+    repo: PathBuf,
+    run_id: String,
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl Checkpointer {
+    /// Start a checkpoint series in `repo`; fails if it isn't a git work tree
+    pub fn new(repo: &Path) -> Result<Self> {
+        let inside = git(repo, &["rev-parse", "--is-inside-work-tree"])
+            .with_context(|| format!("{} is not inside a git repository", repo.display()))?;
+        if inside != "true" {
+            anyhow::bail!("{} is not inside a git work tree", repo.display());
+        }
+        let run_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+            .to_string();
+        Ok(Checkpointer { repo: repo.to_path_buf(), run_id, checkpoints: Vec::new() })
+    }
+
+    /// Commit the current working tree as the checkpoint for `turn`
+    pub fn checkpoint(&mut self, turn: usize) -> Result<&Checkpoint> {
+        let index = self.repo.join(git(&self.repo, &["rev-parse", "--git-path", "leonard-checkpoint-index"])?);
+        let index_str = index.to_string_lossy().to_string();
+        let env = [
+            ("GIT_INDEX_FILE", index_str.as_str()),
+            ("GIT_AUTHOR_NAME", "leonard"),
+            ("GIT_AUTHOR_EMAIL", "leonard@localhost"),
+            ("GIT_COMMITTER_NAME", "leonard"),
+            ("GIT_COMMITTER_EMAIL", "leonard@localhost"),
+        ];
+
+        let head = git(&self.repo, &["rev-parse", "--verify", "--quiet", "HEAD"]).ok();
+        let parent = self.checkpoints.last().map(|c| c.sha.clone()).or(head.clone());
+
+        let result = (|| {
+            let _ = std::fs::remove_file(&index);
+            if let Some(ref head) = head {
+                // Seed the private index from HEAD so `add -A` only has to hash changes
+                git_with_env(&self.repo, &["read-tree", head], &env)?;
+            }
+            git_with_env(&self.repo, &["add", "-A"], &env)?;
+            let tree = git_with_env(&self.repo, &["write-tree"], &env)?;
+
+            let message = format!("leonard: turn {}", turn);
+            let mut commit_args = vec!["commit-tree", tree.as_str(), "-m", message.as_str()];
+            if let Some(ref parent) = parent {
+                commit_args.push("-p");
+                commit_args.push(parent);
+            }
+            git_with_env(&self.repo, &commit_args, &env)
+        })();
+        let _ = std::fs::remove_file(&index);
+        let sha = result?;
+
+        let refname = format!("refs/leonard/{}/turn-{}", self.run_id, turn);
+        git(&self.repo, &["update-ref", &refname, &sha])?;
+
+        self.checkpoints.push(Checkpoint { turn, refname, sha });
+        Ok(self.checkpoints.last().expect("checkpoint was just pushed"))
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_repo(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("leonard-git-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "--quiet"]).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_checkpointer_rejects_non_repo() {
+        let dir = std::env::temp_dir().join(format!("leonard-not-a-repo-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(Checkpointer::new(&dir).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_checkpoints_capture_working_tree_without_touching_index() {
+        let repo = temp_repo("checkpoint");
+        std::fs::write(repo.join("a.txt"), "one").unwrap();
+
+        let mut checkpointer = Checkpointer::new(&repo).unwrap();
+        let first = checkpointer.checkpoint(0).unwrap().clone();
+        std::fs::write(repo.join("a.txt"), "two").unwrap();
+        std::fs::write(repo.join("b.txt"), "new").unwrap();
+        let second = checkpointer.checkpoint(1).unwrap().clone();
+
+        assert_eq!(git(&repo, &["show", &format!("{}:a.txt", first.refname)]).unwrap(), "one");
+        assert_eq!(git(&repo, &["show", &format!("{}:a.txt", second.refname)]).unwrap(), "two");
+        assert_eq!(git(&repo, &["show", &format!("{}:b.txt", second.refname)]).unwrap(), "new");
+        // Second checkpoint builds on the first
+        assert_eq!(git(&repo, &["rev-parse", &format!("{}^", second.refname)]).unwrap(), first.sha);
+        // The user's index is untouched: nothing is staged
+        assert_eq!(git(&repo, &["diff", "--cached", "--name-only"]).unwrap(), "");
+        assert_eq!(checkpointer.checkpoints().len(), 2);
+
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+}
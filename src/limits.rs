@@ -0,0 +1,148 @@
+//! Per-agent resource limits (`--agent-nice`, `--agent-cpu-limit`, `--agent-mem-limit`)
+//!
+//! Applied in the child between fork and exec, so only the spawned `claude`/`codex`
+//! processes (and anything they start) are affected. Linux only.
+
+use anyhow::Result;
+use tokio::process::Command;
+
+use crate::Args;
+
+/// Limits to apply to every spawned agent process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AgentLimits {
+    pub nice: Option<i32>,
+    /// RLIMIT_CPU, in seconds of CPU time
+    pub cpu_seconds: Option<u64>,
+    /// RLIMIT_AS, in bytes of virtual memory
+    pub mem_bytes: Option<u64>,
+}
+
+impl AgentLimits {
+    pub fn from_args(args: &Args) -> Self {
+        AgentLimits {
+            nice: args.agent_nice,
+            cpu_seconds: args.agent_cpu_limit,
+            mem_bytes: args.agent_mem_limit,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == AgentLimits::default()
+    }
+
+    /// Fail early when limits were requested on a platform that can't apply them
+    pub fn validate(&self) -> Result<()> {
+        if !self.is_empty() && !cfg!(target_os = "linux") {
+            anyhow::bail!("--agent-nice, --agent-cpu-limit and --agent-mem-limit are only supported on Linux");
+        }
+        Ok(())
+    }
+
+    /// Arrange for the limits to be set in the child right before it execs
+    #[cfg(target_os = "linux")]
+    pub fn apply(&self, cmd: &mut Command) {
+        if self.is_empty() {
+            return;
+        }
+        let limits = *self;
+        // SAFETY: the closure only makes async-signal-safe syscalls (setpriority, setrlimit)
+        // and does not allocate, as required between fork and exec.
+        unsafe {
+            cmd.pre_exec(move || limits.set_in_child());
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply(&self, _cmd: &mut Command) {}
+
+    #[cfg(target_os = "linux")]
+    fn set_in_child(self) -> std::io::Result<()> {
+        if let Some(nice) = self.nice {
+            // SAFETY: plain syscall on the current (child) process
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if let Some(seconds) = self.cpu_seconds {
+            // SAFETY: `rlimit` returns a valid struct for the duration of the call
+            if unsafe { libc::setrlimit(libc::RLIMIT_CPU, &rlimit(seconds)) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if let Some(bytes) = self.mem_bytes {
+            // SAFETY: as above
+            if unsafe { libc::setrlimit(libc::RLIMIT_AS, &rlimit(bytes)) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Soft and hard limit both at `value`, so the agent can't raise it again
+#[cfg(target_os = "linux")]
+fn rlimit(value: u64) -> libc::rlimit {
+    libc::rlimit { rlim_cur: value as libc::rlim_t, rlim_max: value as libc::rlim_t }
+}
+
+/// Parse a byte size such as `512M`, `2G`, `1536K`, or a plain byte count
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let multiplier = match c.to_ascii_uppercase() {
+                'K' => 1u64 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                'T' => 1 << 40,
+                _ => return Err(format!("unknown size suffix '{}' (use K, M, G or T)", c)),
+            };
+            (&s[..i], multiplier)
+        }
+        _ => (s, 1),
+    };
+    let value: u64 = digits.trim().parse().map_err(|_| format!("invalid size '{}'", s))?;
+    let bytes = value.checked_mul(multiplier).ok_or_else(|| format!("size '{}' is too large", s))?;
+    if bytes == 0 {
+        return Err("size must be greater than zero".to_string());
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // parse_byte_size() tests
+    #[test]
+    fn test_parse_byte_size_suffixes() {
+        assert_eq!(parse_byte_size("1024"), Ok(1024));
+        assert_eq!(parse_byte_size("4k"), Ok(4096));
+        assert_eq!(parse_byte_size("512M"), Ok(512 * 1024 * 1024));
+        assert_eq!(parse_byte_size("2G"), Ok(2 * 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_parse_byte_size_rejects_bad_input() {
+        assert!(parse_byte_size("").is_err());
+        assert!(parse_byte_size("0").is_err());
+        assert!(parse_byte_size("12X").is_err());
+        assert!(parse_byte_size("lots").is_err());
+        assert!(parse_byte_size("99999999999T").is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_limits_are_applied_to_child() {
+        let limits = AgentLimits { nice: Some(5), cpu_seconds: Some(100), mem_bytes: None };
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("ulimit -t; cat /proc/self/stat | cut -d' ' -f19");
+        limits.apply(&mut cmd);
+        let output = cmd.output().await.unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines[0], "100");
+        assert!(lines[1].trim().parse::<i32>().unwrap() >= 5);
+    }
+}
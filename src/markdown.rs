@@ -0,0 +1,150 @@
+//! Markdown rendering shared by `--markdown-transcript` and `--output gh-comment`
+//!
+//! Agent replies are Markdown already and are passed through, with any code fence they
+//! leave open closed. Commands and stderr are wrapped in fences longer than any backtick
+//! run inside them, so their content can't end the block early.
+
+use crate::output::Role;
+use crate::transcript::{Activity, TranscriptEntry};
+
+fn label(role: Role) -> &'static str {
+    match role {
+        Role::Driver => "Driver",
+        Role::Navigator => "Navigator",
+    }
+}
+
+/// The whole session as a readable document: one section per reply or failure
+pub fn render_transcript(entries: &[TranscriptEntry]) -> String {
+    let mut doc = String::from("# Leonard session\n");
+    for entry in entries {
+        match entry {
+            TranscriptEntry::Message { turn, role, timestamp, text, activity } => {
+                doc.push_str(&format!("\n## {} (turn {})\n\n", label(*role), turn));
+                if !timestamp.is_empty() {
+                    doc.push_str(&format!("_{}_\n\n", timestamp));
+                }
+                for item in activity {
+                    doc.push_str(&render_activity(item));
+                }
+                if !text.trim().is_empty() {
+                    doc.push_str(&close_open_fence(text.trim()));
+                    doc.push('\n');
+                }
+            }
+            TranscriptEntry::Error { turn, role, message, stderr_tail, .. } => {
+                doc.push_str(&format!("\n## {} (turn {}) failed\n\n{}\n", label(*role), turn, message));
+                if !stderr_tail.is_empty() {
+                    doc.push('\n');
+                    doc.push_str(&code_block("text", &stderr_tail.join("\n")));
+                }
+            }
+        }
+    }
+    doc
+}
+
+fn render_activity(item: &Activity) -> String {
+    match item {
+        Activity::Reasoning { text } => {
+            let quoted: Vec<String> =
+                text.trim().lines().map(|l| if l.is_empty() { ">".to_string() } else { format!("> {}", l) }).collect();
+            format!("{}\n\n", quoted.join("\n"))
+        }
+        Activity::ToolCall { name, result } => match result {
+            Some(result) => format!("- `{}` → {}\n\n", name, result),
+            None => format!("- `{}`\n\n", name),
+        },
+        Activity::Command { command, exit_code, summary } => {
+            let mut body = format!("$ {}", command);
+            if !summary.is_empty() {
+                body.push_str(&format!("\n{}", summary));
+            }
+            format!("{}_exit {}_\n\n", code_block("console", &body), exit_code)
+        }
+    }
+}
+
+/// `text` in a fenced block whose fence outlasts every backtick run inside it
+pub fn code_block(lang: &str, text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}{lang}\n{text}\n{fence}\n", fence = fence, lang = lang, text = text.trim_end())
+}
+
+/// If `text` ends inside a fenced code block, close it, so the fence can't swallow
+/// whatever is rendered after it
+pub fn close_open_fence(text: &str) -> String {
+    // The opening fence's character and length; a closer must match the char and be at least as long
+    let mut open: Option<(char, usize)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start_matches(' ');
+        if line.len() - trimmed.len() > 3 {
+            continue;
+        }
+        let Some(c) = trimmed.chars().next().filter(|c| *c == '`' || *c == '~') else { continue };
+        let run = trimmed.chars().take_while(|&ch| ch == c).count();
+        if run < 3 {
+            continue;
+        }
+        match open {
+            None => open = Some((c, run)),
+            Some((oc, olen)) if oc == c && run >= olen && trimmed[run..].trim().is_empty() => open = None,
+            Some(_) => {}
+        }
+    }
+    match open {
+        Some((c, len)) => format!("{}\n{}", text, c.to_string().repeat(len)),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // render_transcript() tests
+    #[test]
+    fn test_render_transcript_sections() {
+        let entries = vec![
+            TranscriptEntry::Message {
+                turn: 0,
+                role: Role::Navigator,
+                timestamp: String::new(),
+                text: "Add a test.".to_string(),
+                activity: vec![
+                    Activity::Reasoning { text: "Check the tests\n\nthen the docs".to_string() },
+                    Activity::Command { command: "cargo test".to_string(), exit_code: 101, summary: "2 lines".to_string() },
+                ],
+            },
+            TranscriptEntry::Error {
+                turn: 1,
+                role: Role::Driver,
+                timestamp: String::new(),
+                message: "driver exited with status: 1".to_string(),
+                stderr_tail: vec!["rate limited".to_string()],
+            },
+        ];
+        let doc = render_transcript(&entries);
+        assert!(doc.starts_with("# Leonard session\n\n## Navigator (turn 0)\n\n"));
+        assert!(doc.contains("> Check the tests\n>\n> then the docs\n\n"));
+        assert!(doc.contains("```console\n$ cargo test\n2 lines\n```\n_exit 101_\n\nAdd a test.\n"));
+        assert!(doc.contains("## Driver (turn 1) failed\n\ndriver exited with status: 1\n\n```text\nrate limited\n```\n"));
+    }
+
+    // code_block() tests
+    #[test]
+    fn test_code_block_outlasts_backticks() {
+        assert_eq!(code_block("", "plain"), "```\nplain\n```\n");
+        assert_eq!(code_block("md", "```rust\nx\n```"), "````md\n```rust\nx\n```\n````\n");
+    }
+
+    // close_open_fence() tests
+    #[test]
+    fn test_close_open_fence() {
+        assert_eq!(close_open_fence("a\n```\ncode"), "a\n```\ncode\n```");
+        assert_eq!(close_open_fence("````md\n```\ninner\n```"), "````md\n```\ninner\n```\n````");
+        assert_eq!(close_open_fence("~~~\nx\n~~~"), "~~~\nx\n~~~");
+        assert_eq!(close_open_fence("inline ``` not a fence"), "inline ``` not a fence");
+    }
+}
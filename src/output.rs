@@ -0,0 +1,650 @@
+//! Relay events and the formatters that write them to stdout
+//!
+//! Agent runners and the relay loop describe what happened as `RelayEvent`s; an
+//! `Output` decides how they look: colored text for a terminal (`human`), a clean
+//! speaker-labelled conversation (`dialogue`), or Server-Sent Events frames for a
+//! browser `EventSource` (`sse`). `gh-comment` prints nothing live; see `gh_comment`.
+
+use colored::{Color, Colorize};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::io::Write as _;
+
+use crate::theme::Theme;
+use crate::transcript::Activity;
+use crate::{log_line, maybe_color, strip_ansi, truncate_line, Deadline};
+
+/// How relay events are written to stdout
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Colored, human-readable transcript
+    #[default]
+    Human,
+    /// Only the agents' messages, as an alternating conversation with speaker labels
+    Dialogue,
+    /// Server-Sent Events frames (`event: <type>` + `data: <json>`)
+    Sse,
+    /// Nothing while the relay runs, then a GitHub PR comment body rendered from the transcript
+    GhComment,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Driver,
+    Navigator,
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Driver => "driver",
+            Role::Navigator => "navigator",
+        }
+    }
+}
+
+/// Something worth showing while the relay runs
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RelayEvent {
+    /// An agent is about to run; `turn` is None for the opening driver run
+    TurnStart { role: Role, turn: Option<usize>, retry: bool },
+    /// The agent's run finished
+    TurnEnd { role: Role },
+    /// The exact prompt being sent (only with --show-prompts)
+    Prompt { role: Role, text: String },
+    /// Reply text from an agent
+    Text { role: Role, text: String },
+    /// Reasoning summary from an agent
+    Thinking { role: Role, text: String },
+    /// The agent invoked a tool
+    ToolUse { role: Role, name: String },
+    /// Summary of a tool's result
+    ToolResult { role: Role, summary: String },
+    /// The agent started a shell command; a `Command` follows once it finishes
+    CommandStarted { role: Role, command: String },
+    /// The agent ran a shell command
+    Command { role: Role, command: String, exit_code: i32, summary: String },
+    /// What an agent run cost, as the agent reported it
+    Cost { role: Role, usd: f64 },
+    /// An agent's complete reply for a turn, as recorded in the transcript, and how many
+    /// tool calls and commands it took
+    Reply { role: Role, turn: usize, text: String, tool_calls: usize },
+    /// An agent signalled ALL_DONE; `rationale` is empty unless it explained itself
+    DoneSignaled { role: Role, rationale: String },
+    /// The relay ended
+    Done { turns: usize, reason: FinishReason },
+}
+
+/// Why the relay loop ended
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// An agent signalled ALL_DONE (and --verify-on-done, if set, passed)
+    AllDone,
+    /// --max-turns was reached
+    MaxTurns,
+    /// The agents kept disagreeing about completion (--max-done-disagreements)
+    Disagreement,
+    /// The navigator had no feedback and --on-empty-navigator is `stop`
+    EmptyNavigator,
+    /// --stop-at-output-limit after --max-total-output-bytes was reached
+    OutputLimit,
+    /// The reported cost passed --cost-limit
+    CostLimit,
+    /// A --driver-only or --navigator-only run finished its single turn
+    SmokeTest,
+    /// --deadline passed, and whichever agent was running was killed
+    Deadline,
+}
+
+impl FinishReason {
+    /// The reason as it appears in the `done` event
+    pub fn name(self) -> &'static str {
+        match self {
+            FinishReason::AllDone => "all_done",
+            FinishReason::MaxTurns => "max_turns",
+            FinishReason::Disagreement => "disagreement",
+            FinishReason::EmptyNavigator => "empty_navigator",
+            FinishReason::OutputLimit => "output_limit",
+            FinishReason::CostLimit => "cost_limit",
+            FinishReason::SmokeTest => "smoke_test",
+            FinishReason::Deadline => "deadline",
+        }
+    }
+}
+
+impl RelayEvent {
+    /// Turn boundaries stay visible after --max-total-output-bytes; everything else is detail
+    fn is_detail(&self) -> bool {
+        !matches!(
+            self,
+            RelayEvent::TurnStart { .. } | RelayEvent::TurnEnd { .. } | RelayEvent::Cost { .. } | RelayEvent::Done { .. }
+        )
+    }
+
+    /// SSE `event:` name, matching the `type` field of the JSON payload
+    fn name(&self) -> &'static str {
+        match self {
+            RelayEvent::TurnStart { .. } => "turn_start",
+            RelayEvent::TurnEnd { .. } => "turn_end",
+            RelayEvent::Prompt { .. } => "prompt",
+            RelayEvent::Text { .. } => "text",
+            RelayEvent::Thinking { .. } => "thinking",
+            RelayEvent::ToolUse { .. } => "tool_use",
+            RelayEvent::ToolResult { .. } => "tool_result",
+            RelayEvent::CommandStarted { .. } => "command_started",
+            RelayEvent::Command { .. } => "command",
+            RelayEvent::Cost { .. } => "cost",
+            RelayEvent::Reply { .. } => "reply",
+            RelayEvent::DoneSignaled { .. } => "done_signaled",
+            RelayEvent::Done { .. } => "done",
+        }
+    }
+}
+
+/// Receives every relay event in place of stdout, for embedding the relay (see `run_session`)
+pub type EventListener = Box<dyn Fn(&RelayEvent)>;
+
+/// Writes relay events to stdout in the selected format
+pub struct Output {
+    format: OutputFormat,
+    /// Total stdout budget for the run (--max-total-output-bytes; 0 = unlimited)
+    limit: usize,
+    written: Cell<usize>,
+    exhausted: Cell<bool>,
+    /// Reasoning, tool calls, and commands since the last `take_activity`, for the transcript
+    activity: RefCell<Vec<Activity>>,
+    /// Sum of every reported agent cost, in US dollars
+    cost: Cell<f64>,
+    /// ANSI-stripped copy of everything written to stdout (--output-file); dropped after a write error
+    tee: RefCell<Option<std::fs::File>>,
+    /// Takes the events instead of stdout when set; the format and budget then don't apply
+    listener: Option<EventListener>,
+    /// --quiet: human output shows a summary line per reply instead of the stream
+    quiet: bool,
+    /// Each agent's colors in human and dialogue output (--theme)
+    theme: Theme,
+    /// When this session must end (--deadline); agent runs and prompts race it
+    deadline: Option<Deadline>,
+    /// Whether this session's output is colored (--color, else the environment)
+    colors: bool,
+}
+
+impl Output {
+    pub fn with_limit(format: OutputFormat, limit: usize) -> Self {
+        Output { format, limit, written: Cell::new(0), exhausted: Cell::new(false), activity: RefCell::new(Vec::new()), cost: Cell::new(0.0), tee: RefCell::new(None), listener: None, quiet: false, theme: Theme::default(), deadline: None, colors: false }
+    }
+
+    /// Also write everything to `file`, without ANSI escapes (--output-file)
+    pub fn with_tee(self, file: Option<std::fs::File>) -> Self {
+        *self.tee.borrow_mut() = file;
+        self
+    }
+
+    /// With human output, print a summary line per reply instead of streaming it (--quiet)
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Show each agent's output in `theme`'s colors (--theme)
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Color human and dialogue output (`Args::colors`)
+    pub fn with_colors(mut self, colors: bool) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub(crate) fn colors(&self) -> bool {
+        self.colors
+    }
+
+    /// End agent runs and prompts at `deadline` (--deadline)
+    pub(crate) fn with_deadline(mut self, deadline: Option<Deadline>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Deadline> {
+        self.deadline
+    }
+
+    /// Hand every event to `listener` rather than printing anything
+    pub fn with_listener(mut self, listener: Option<EventListener>) -> Self {
+        self.listener = listener;
+        self
+    }
+
+    /// Whether the output budget has run out and detail is being suppressed
+    pub fn exhausted(&self) -> bool {
+        self.exhausted.get()
+    }
+
+    /// Total reported cost so far, in US dollars
+    pub fn total_cost(&self) -> f64 {
+        self.cost.get()
+    }
+
+    /// The activity recorded since the last call, i.e. behind the reply that just finished
+    pub fn take_activity(&self) -> Vec<Activity> {
+        self.activity.take()
+    }
+
+    pub fn emit(&self, event: RelayEvent) {
+        self.record_activity(&event);
+        if let Some(ref listener) = self.listener {
+            listener(&event);
+            return;
+        }
+        let rendered = self.render(&event);
+        if rendered.is_empty() || !self.admit(&event, rendered.len()) {
+            return;
+        }
+        self.print(&rendered);
+    }
+
+    /// Write `text` to stdout and the --output-file, if any, flushing both; nothing with a listener
+    pub fn print(&self, text: &str) {
+        if self.listener.is_some() {
+            return;
+        }
+        let mut out = std::io::stdout().lock();
+        let _ = out.write_all(text.as_bytes());
+        let _ = out.flush();
+        self.write_tee(text);
+    }
+
+    fn write_tee(&self, text: &str) {
+        let mut tee = self.tee.borrow_mut();
+        let Some(file) = tee.as_mut() else { return };
+        let result = file.write_all(strip_ansi(text).as_bytes()).and_then(|_| file.flush());
+        if let Err(e) = result {
+            log_line("system", &format!("warning: failed to write --output-file: {}; no longer writing it", e));
+            *tee = None;
+        }
+    }
+
+    /// Keep reasoning, tool calls, commands, and costs regardless of format or budget
+    fn record_activity(&self, event: &RelayEvent) {
+        let mut activity = self.activity.borrow_mut();
+        match event {
+            RelayEvent::Thinking { text, .. } => activity.push(Activity::Reasoning { text: text.clone() }),
+            RelayEvent::ToolUse { name, .. } => activity.push(Activity::ToolCall { name: name.clone(), result: None }),
+            RelayEvent::ToolResult { summary, .. } => {
+                // Results arrive after their call; attach to the latest call still waiting for one
+                let pending = activity.iter_mut().rev().find_map(|a| match a {
+                    Activity::ToolCall { result: result @ None, .. } => Some(result),
+                    _ => None,
+                });
+                if let Some(result) = pending {
+                    *result = Some(summary.clone());
+                }
+            }
+            RelayEvent::Cost { usd, .. } => self.cost.set(self.cost.get() + usd),
+            RelayEvent::Command { command, exit_code, summary, .. } => activity.push(Activity::Command {
+                command: command.clone(),
+                exit_code: *exit_code,
+                summary: summary.clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    /// Charge `len` bytes against the budget; false if the event should be dropped
+    fn admit(&self, event: &RelayEvent, len: usize) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+        if event.is_detail() {
+            if self.exhausted.get() {
+                return false;
+            }
+            if self.written.get() + len > self.limit {
+                self.exhausted.set(true);
+                log_line(
+                    "system",
+                    &format!("--max-total-output-bytes ({}) reached; showing turn headers only from here on", self.limit),
+                );
+                return false;
+            }
+        }
+        self.written.set(self.written.get() + len);
+        true
+    }
+
+    /// The exact text `emit` writes for `event`
+    fn render(&self, event: &RelayEvent) -> String {
+        match self.format {
+            OutputFormat::Human if self.quiet => render_quiet(event, &self.theme, self.colors),
+            OutputFormat::Human => render_human(event, &self.theme, self.colors),
+            OutputFormat::Dialogue => render_dialogue(event, &self.theme, self.colors),
+            OutputFormat::Sse => render_sse(event),
+            // The comment is rendered from the transcript once the relay ends
+            OutputFormat::GhComment => String::new(),
+        }
+    }
+}
+
+fn render_sse(event: &RelayEvent) -> String {
+    let data = serde_json::to_string(event).unwrap_or_else(|_| "{}".to_string());
+    format!("event: {}\ndata: {}\n\n", event.name(), data)
+}
+
+/// Separates turns in dialogue mode
+const DIALOGUE_RULE: &str = "------------------------------------------------------------";
+
+fn render_dialogue(event: &RelayEvent, theme: &Theme, colors: bool) -> String {
+    match event {
+        RelayEvent::TurnStart { role, turn, retry } => {
+            let mut label = match role {
+                Role::Driver => "Driver".to_string(),
+                Role::Navigator => "Navigator".to_string(),
+            };
+            if *retry {
+                label.push_str(" (retry)");
+            }
+            let label = maybe_color(colors, format!("{}:", label), |s| s.color(theme.header(*role)).bold());
+            // The opening driver run has no turn number; every later turn gets a rule above it
+            if turn.is_none() {
+                format!("{}\n\n", label)
+            } else {
+                format!("\n{}\n\n{}\n\n", DIALOGUE_RULE, label)
+            }
+        }
+        RelayEvent::Text { text, .. } => format!("{}\n", text),
+        // Everything else is tool activity or diagnostics, not conversation
+        _ => String::new(),
+    }
+}
+
+/// Commands that failed stand out in red; passing ones keep the navigator's command color
+fn command_color(exit_code: i32) -> Color {
+    if exit_code == 0 {
+        Color::BrightMagenta
+    } else {
+        Color::Red
+    }
+}
+
+fn render_human(event: &RelayEvent, theme: &Theme, colors: bool) -> String {
+    match event {
+        RelayEvent::TurnStart { role, turn, retry } => {
+            let label = role.name().to_uppercase();
+            let header = match (turn, retry) {
+                (None, _) => format!("=== {} ===", label),
+                (Some(turn), false) => format!("=== {} (turn {}) ===", label, turn),
+                (Some(turn), true) => format!("=== {} (turn {}, retry) ===", label, turn),
+            };
+            format!("{}\n", maybe_color(colors, header, |s| s.color(theme.header(*role)).bold()))
+        }
+        RelayEvent::TurnEnd { .. } => "\n".to_string(),
+        RelayEvent::Prompt { role, text } => format!(
+            "{}\n{}\n{}\n",
+            maybe_color(colors, format!("--- sending to {} ---", role.name()), |s| s.dimmed()),
+            maybe_color(colors, text.as_str(), |s| s.dimmed()),
+            maybe_color(colors, format!("--- end of {} prompt ---", role.name()), |s| s.dimmed()),
+        ),
+        RelayEvent::Text { role, text } => format!("{}\n", maybe_color(colors, text.as_str(), |s| s.color(theme.body(*role)))),
+        RelayEvent::Thinking { text, .. } => text
+            .lines()
+            .map(|l| format!("{}\n", maybe_color(colors, format!("  thinking: {}", truncate_line(l, 80)), |s| s.magenta().dimmed())))
+            .collect(),
+        // No newline: the tool's result summary follows on the same line
+        RelayEvent::ToolUse { name, .. } => maybe_color(colors, format!("  [{}] ", name), |s| s.bright_cyan()),
+        RelayEvent::ToolResult { summary, .. } => {
+            format!("{}\n", maybe_color(colors, format!("  -> {}", summary), |s| s.cyan().dimmed()))
+        }
+        RelayEvent::CommandStarted { command, .. } => {
+            format!("{}\n", maybe_color(colors, format!("  running: {}", truncate_line(command, 70)), |s| s.bright_magenta().dimmed()))
+        }
+        RelayEvent::Command { command, exit_code, summary, .. } => {
+            let line = if summary.is_empty() {
+                format!("  [exit {}] {}", exit_code, truncate_line(command, 60))
+            } else {
+                format!("  [exit {}] {} -> {}", exit_code, truncate_line(command, 40), truncate_line(summary, 30))
+            };
+            format!("{}\n", maybe_color(colors, line, |s| s.color(command_color(*exit_code))))
+        }
+        // The reply was already streamed as text; the rest is logged to stderr by the relay loop
+        RelayEvent::Cost { .. } | RelayEvent::Reply { .. } | RelayEvent::DoneSignaled { .. } | RelayEvent::Done { .. } => {
+            String::new()
+        }
+    }
+}
+
+/// How much of a reply a --quiet summary line shows
+const QUIET_PREVIEW_CHARS: usize = 200;
+
+/// --quiet: one line per reply, with its size, tool calls, and opening words. Prompts are
+/// only emitted when asked for (`Args::show_prompts`), so they're shown as in human output
+fn render_quiet(event: &RelayEvent, theme: &Theme, colors: bool) -> String {
+    match event {
+        RelayEvent::Reply { role, turn, text, tool_calls } => {
+            let preview = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let line = format!(
+                "[{} turn {}] {} bytes, {} tool call(s): {}",
+                role.name(),
+                turn,
+                text.len(),
+                tool_calls,
+                truncate_line(&preview, QUIET_PREVIEW_CHARS)
+            );
+            format!("{}\n", maybe_color(colors, line, |s| s.color(theme.body(*role))))
+        }
+        RelayEvent::Prompt { .. } => render_human(event, theme, colors),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn human(event: RelayEvent) -> String {
+        strip_ansi(&Output::with_limit(OutputFormat::Human, 0).render(&event))
+    }
+
+    fn dialogue(event: RelayEvent) -> String {
+        strip_ansi(&Output::with_limit(OutputFormat::Dialogue, 0).render(&event))
+    }
+
+    fn sse(event: RelayEvent) -> String {
+        Output::with_limit(OutputFormat::Sse, 0).render(&event)
+    }
+
+    // render_human() tests
+    #[test]
+    fn test_human_turn_headers() {
+        assert_eq!(human(RelayEvent::TurnStart { role: Role::Driver, turn: None, retry: false }), "=== DRIVER ===\n");
+        assert_eq!(
+            human(RelayEvent::TurnStart { role: Role::Navigator, turn: Some(2), retry: true }),
+            "=== NAVIGATOR (turn 2, retry) ===\n"
+        );
+    }
+
+    #[test]
+    fn test_human_tool_use_stays_on_line() {
+        assert_eq!(human(RelayEvent::ToolUse { role: Role::Driver, name: "Read".into() }), "  [Read] ");
+        assert_eq!(human(RelayEvent::ToolResult { role: Role::Driver, summary: "ok".into() }), "  -> ok\n");
+    }
+
+    #[test]
+    fn test_human_command_and_thinking() {
+        let event = RelayEvent::Command {
+            role: Role::Navigator,
+            command: "cargo test".into(),
+            exit_code: 1,
+            summary: String::new(),
+        };
+        assert_eq!(human(event), "  [exit 1] cargo test\n");
+        let event = RelayEvent::Thinking { role: Role::Navigator, text: "a\nb".into() };
+        assert_eq!(human(event), "  thinking: a\n  thinking: b\n");
+    }
+
+    #[test]
+    fn test_command_color_marks_failures() {
+        assert_eq!(command_color(0), Color::BrightMagenta);
+        assert_eq!(command_color(1), Color::Red);
+        assert_eq!(command_color(-1), Color::Red);
+    }
+
+    #[test]
+    fn test_human_done_is_silent() {
+        assert_eq!(human(RelayEvent::Done { turns: 3, reason: FinishReason::MaxTurns }), "");
+        assert_eq!(human(RelayEvent::Reply { role: Role::Driver, turn: 1, text: "x".into(), tool_calls: 0 }), "");
+    }
+
+    // render_dialogue() tests
+    #[test]
+    fn test_dialogue_labels_and_rules() {
+        assert_eq!(dialogue(RelayEvent::TurnStart { role: Role::Driver, turn: None, retry: false }), "Driver:\n\n");
+        let navigator = dialogue(RelayEvent::TurnStart { role: Role::Navigator, turn: Some(0), retry: false });
+        assert_eq!(navigator, format!("\n{}\n\nNavigator:\n\n", DIALOGUE_RULE));
+        let retry = dialogue(RelayEvent::TurnStart { role: Role::Navigator, turn: Some(0), retry: true });
+        assert!(retry.ends_with("Navigator (retry):\n\n"));
+    }
+
+    #[test]
+    fn test_dialogue_shows_only_messages() {
+        assert_eq!(dialogue(RelayEvent::Text { role: Role::Driver, text: "hello".into() }), "hello\n");
+        assert_eq!(dialogue(RelayEvent::ToolUse { role: Role::Driver, name: "Read".into() }), "");
+        assert_eq!(dialogue(RelayEvent::Thinking { role: Role::Navigator, text: "hmm".into() }), "");
+        assert_eq!(dialogue(RelayEvent::Prompt { role: Role::Driver, text: "p".into() }), "");
+        assert_eq!(dialogue(RelayEvent::TurnEnd { role: Role::Driver }), "");
+    }
+
+    // admit() tests
+    #[test]
+    fn test_output_limit_suppresses_detail_but_keeps_headers() {
+        let out = Output::with_limit(OutputFormat::Human, 10);
+        let text = RelayEvent::Text { role: Role::Driver, text: "hello".into() };
+        let header = RelayEvent::TurnStart { role: Role::Navigator, turn: Some(1), retry: false };
+        assert!(out.admit(&text, 6));
+        assert!(!out.exhausted());
+        assert!(!out.admit(&text, 6));
+        assert!(out.exhausted());
+        assert!(!out.admit(&text, 1));
+        assert!(out.admit(&header, 30));
+    }
+
+    #[test]
+    fn test_output_unlimited_by_default() {
+        let out = Output::with_limit(OutputFormat::Human, 0);
+        let text = RelayEvent::Text { role: Role::Driver, text: "x".into() };
+        assert!(out.admit(&text, usize::MAX / 2));
+        assert!(out.admit(&text, usize::MAX / 2));
+        assert!(!out.exhausted());
+    }
+
+    // take_activity() tests
+    #[test]
+    fn test_activity_is_recorded_and_drained() {
+        let out = Output::with_limit(OutputFormat::Dialogue, 1);
+        out.emit(RelayEvent::ToolUse { role: Role::Driver, name: "Read".into() });
+        out.emit(RelayEvent::ToolResult { role: Role::Driver, summary: "3 lines".into() });
+        out.emit(RelayEvent::Text { role: Role::Driver, text: "done".into() });
+        out.emit(RelayEvent::Command { role: Role::Driver, command: "ls".into(), exit_code: 2, summary: String::new() });
+        assert_eq!(
+            out.take_activity(),
+            vec![
+                Activity::ToolCall { name: "Read".into(), result: Some("3 lines".into()) },
+                Activity::Command { command: "ls".into(), exit_code: 2, summary: String::new() },
+            ]
+        );
+        assert!(out.take_activity().is_empty());
+    }
+
+    #[test]
+    fn test_cost_is_totalled() {
+        let out = Output::with_limit(OutputFormat::Human, 0);
+        assert_eq!(out.total_cost(), 0.0);
+        out.emit(RelayEvent::Cost { role: Role::Driver, usd: 0.25 });
+        out.emit(RelayEvent::Cost { role: Role::Driver, usd: 0.5 });
+        assert_eq!(out.total_cost(), 0.75);
+    }
+
+    // render_sse() tests
+    #[test]
+    fn test_sse_frame_has_event_name_and_json_data() {
+        let frame = sse(RelayEvent::TurnStart { role: Role::Navigator, turn: Some(1), retry: false });
+        assert_eq!(
+            frame,
+            "event: turn_start\ndata: {\"type\":\"turn_start\",\"role\":\"navigator\",\"turn\":1,\"retry\":false}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_sse_multiline_text_stays_on_one_data_line() {
+        let frame = sse(RelayEvent::Text { role: Role::Driver, text: "line one\nline two".into() });
+        assert!(frame.starts_with("event: text\ndata: "));
+        assert_eq!(frame.matches('\n').count(), 3);
+        assert!(frame.contains("line one\\nline two"));
+    }
+
+    #[test]
+    fn test_sse_done() {
+        assert_eq!(
+            sse(RelayEvent::Done { turns: 4, reason: FinishReason::AllDone }),
+            "event: done\ndata: {\"type\":\"done\",\"turns\":4,\"reason\":\"all_done\"}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_sse_structured_events() {
+        let frame = sse(RelayEvent::DoneSignaled { role: Role::Navigator, rationale: String::new() });
+        assert_eq!(frame, "event: done_signaled\ndata: {\"type\":\"done_signaled\",\"role\":\"navigator\",\"rationale\":\"\"}\n\n");
+        let frame = sse(RelayEvent::Reply { role: Role::Driver, turn: 2, text: "fixed".into(), tool_calls: 1 });
+        assert!(frame.starts_with("event: reply\ndata: {\"type\":\"reply\",\"role\":\"driver\",\"turn\":2,"));
+    }
+
+    // with_colors() tests
+    #[test]
+    fn test_colors_are_per_output() {
+        let event = RelayEvent::Text { role: Role::Driver, text: "Plan".into() };
+        let colored = Output::with_limit(OutputFormat::Human, 0).with_colors(true);
+        let plain = Output::with_limit(OutputFormat::Human, 0).with_colors(false);
+        assert!(colored.render(&event).contains("\x1b["));
+        assert_eq!(plain.render(&event), "Plan\n");
+    }
+
+    // render_quiet() tests
+    #[test]
+    fn test_quiet_summarizes_replies_only() {
+        let quiet = |event| strip_ansi(&Output::with_limit(OutputFormat::Human, 0).with_quiet(true).render(&event));
+        assert_eq!(quiet(RelayEvent::Text { role: Role::Driver, text: "Plan".into() }), "");
+        assert_eq!(quiet(RelayEvent::TurnStart { role: Role::Driver, turn: None, retry: false }), "");
+        assert_eq!(
+            quiet(RelayEvent::Reply { role: Role::Navigator, turn: 2, text: "Add a test\nfor parse()".into(), tool_calls: 3 }),
+            "[navigator turn 2] 22 bytes, 3 tool call(s): Add a test for parse()\n"
+        );
+        let long = quiet(RelayEvent::Reply { role: Role::Driver, turn: 0, text: "x".repeat(300), tool_calls: 0 });
+        assert!(long.ends_with(&format!("{}...\n", "x".repeat(200))));
+    }
+
+    #[test]
+    fn test_quiet_shows_requested_prompts() {
+        let out = Output::with_limit(OutputFormat::Human, 0).with_quiet(true);
+        let prompt = strip_ansi(&out.render(&RelayEvent::Prompt { role: Role::Navigator, text: "Review this".into() }));
+        assert_eq!(prompt, "--- sending to navigator ---\nReview this\n--- end of navigator prompt ---\n");
+    }
+
+    // with_tee() tests
+    #[test]
+    fn test_tee_file_gets_plain_text() {
+        let path = std::env::temp_dir().join(format!("leonard-output-test-{}.log", std::process::id()));
+        let out = Output::with_limit(OutputFormat::Human, 0).with_tee(Some(std::fs::File::create(&path).unwrap()));
+        out.emit(RelayEvent::TurnStart { role: Role::Driver, turn: Some(1), retry: false });
+        out.emit(RelayEvent::Text { role: Role::Driver, text: "\x1b[31mred\x1b[0m".to_string() });
+        // Written per event, so a killed run keeps what it had so far
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "=== DRIVER (turn 1) ===\nred\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+}
@@ -0,0 +1,551 @@
+//! `--done-regex`: a small regular-expression matcher for completion phrases
+//!
+//! Supports the everyday subset: literals and `\` escapes, `.`, classes (`[a-z]`,
+//! `[^,]`, `\d \w \s` and their negations), groups (`(...)`, `(?:...)`), `|`,
+//! `* + ?` and `{n}`/`{n,}`/`{n,m}` (a trailing `?` for laziness is accepted), the
+//! assertions `^ $ \b \B`, and a leading `(?i)` for case-insensitive matching. `^` and
+//! `$` match at line boundaries and `.` doesn't match a newline. Backreferences and
+//! lookaround are rejected, as are stacked quantifiers (`a**`; group them instead).
+//!
+//! Patterns compile to a small instruction list run as a Pike VM, so matching is
+//! linear in the reply's length however the pattern is written.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Largest `{n}`/`{n,m}` count, since each repetition is compiled out
+const MAX_REPEAT: u32 = 1000;
+
+/// Largest compiled program; nested counted repeats multiply, so `MAX_REPEAT` alone doesn't bound it
+const MAX_PROGRAM_LEN: usize = 100_000;
+
+/// Deepest group nesting; parsing and compiling recurse once per level
+const MAX_NESTING: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ast {
+    Empty,
+    Literal(char),
+    AnyButNewline,
+    Class(Class),
+    Assert(Assertion),
+    Concat(Vec<Ast>),
+    Alternate(Vec<Ast>),
+    Repeat { ast: Box<Ast>, min: u32, max: Option<u32> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assertion {
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn of(ranges: &[(char, char)], negated: bool) -> Self {
+        Class { ranges: ranges.to_vec(), negated }
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    /// Groups currently open
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, c: char) -> bool {
+        self.chars.next_if_eq(&c).is_some()
+    }
+
+    fn alternation(&mut self) -> Result<Ast> {
+        let mut branches = vec![self.concat()?];
+        while self.eat('|') {
+            branches.push(self.concat()?);
+        }
+        Ok(if branches.len() == 1 { branches.remove(0) } else { Ast::Alternate(branches) })
+    }
+
+    fn concat(&mut self) -> Result<Ast> {
+        let mut items = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            items.push(self.repeat(atom)?);
+        }
+        Ok(match items.len() {
+            0 => Ast::Empty,
+            1 => items.remove(0),
+            _ => Ast::Concat(items),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Ast> {
+        let Some(c) = self.chars.next() else { anyhow::bail!("unexpected end of pattern") };
+        Ok(match c {
+            '.' => Ast::AnyButNewline,
+            '^' => Ast::Assert(Assertion::LineStart),
+            '$' => Ast::Assert(Assertion::LineEnd),
+            '[' => Ast::Class(self.class()?),
+            '\\' => self.escape(false)?,
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    anyhow::bail!("only (?:...) groups and a leading (?i) are supported");
+                }
+                if self.depth == MAX_NESTING {
+                    anyhow::bail!("groups nested more than {} deep are not supported", MAX_NESTING);
+                }
+                self.depth += 1;
+                let inner = self.alternation()?;
+                self.depth -= 1;
+                if !self.eat(')') {
+                    anyhow::bail!("unclosed group");
+                }
+                inner
+            }
+            ')' => anyhow::bail!("unmatched )"),
+            '*' | '+' | '?' | '{' => anyhow::bail!("{} has nothing to repeat", c),
+            c => Ast::Literal(c),
+        })
+    }
+
+    /// After a `\`; `in_class` disallows the assertions
+    fn escape(&mut self, in_class: bool) -> Result<Ast> {
+        let Some(c) = self.chars.next() else { anyhow::bail!("pattern ends with \\") };
+        Ok(match c {
+            'd' => Ast::Class(Class::of(DIGIT, false)),
+            'D' => Ast::Class(Class::of(DIGIT, true)),
+            'w' => Ast::Class(Class::of(WORD, false)),
+            'W' => Ast::Class(Class::of(WORD, true)),
+            's' => Ast::Class(Class::of(SPACE, false)),
+            'S' => Ast::Class(Class::of(SPACE, true)),
+            'b' if !in_class => Ast::Assert(Assertion::WordBoundary),
+            'B' if !in_class => Ast::Assert(Assertion::NotWordBoundary),
+            'n' => Ast::Literal('\n'),
+            'r' => Ast::Literal('\r'),
+            't' => Ast::Literal('\t'),
+            c if c.is_ascii_digit() => anyhow::bail!("backreferences are not supported"),
+            c if c.is_ascii_alphanumeric() => anyhow::bail!("unknown escape \\{}", c),
+            c => Ast::Literal(c),
+        })
+    }
+
+    /// After a `[`, up to and including the closing `]`
+    fn class(&mut self) -> Result<Class> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let Some(c) = self.chars.next() else { anyhow::bail!("unclosed [") };
+            let lo = match c {
+                ']' if !first => break,
+                '\\' => match self.escape(true)? {
+                    Ast::Literal(c) => c,
+                    Ast::Class(class) if !class.negated => {
+                        ranges.extend(class.ranges);
+                        first = false;
+                        continue;
+                    }
+                    _ => anyhow::bail!("negated classes like \\D can't be used inside [...]"),
+                },
+                c => c,
+            };
+            first = false;
+            // A `-` just before `]` is a literal
+            let hi = if self.chars.peek() == Some(&'-') && self.chars.clone().nth(1).is_some_and(|c| c != ']') {
+                self.chars.next();
+                match self.chars.next() {
+                    Some('\\') => match self.escape(true)? {
+                        Ast::Literal(c) => c,
+                        _ => anyhow::bail!("a class can't end a range"),
+                    },
+                    Some(c) => c,
+                    None => anyhow::bail!("unclosed ["),
+                }
+            } else {
+                lo
+            };
+            if hi < lo {
+                anyhow::bail!("range {}-{} is out of order", lo, hi);
+            }
+            ranges.push((lo, hi));
+        }
+        Ok(Class { ranges, negated })
+    }
+
+    fn repeat(&mut self, atom: Ast) -> Result<Ast> {
+        let (min, max) = match self.chars.next_if(|c| matches!(c, '*' | '+' | '?' | '{')) {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some(_) => self.counts()?,
+            None => return Ok(atom),
+        };
+        // A lazy quantifier; laziness doesn't change whether there's a match
+        self.eat('?');
+        // Each stacked quantifier would nest like a group, without the group's depth limit
+        if self.chars.peek().is_some_and(|c| matches!(c, '*' | '+' | '?' | '{')) {
+            anyhow::bail!("quantifiers can't be stacked; wrap the repeated part in (?:...)");
+        }
+        Ok(Ast::Repeat { ast: Box::new(atom), min, max })
+    }
+
+    /// After a `{`: `n}`, `n,}`, or `n,m}`
+    fn counts(&mut self) -> Result<(u32, Option<u32>)> {
+        let min = self.number()?.ok_or_else(|| anyhow::anyhow!("expected a count after {{"))?;
+        let max = if self.eat(',') { self.number()? } else { Some(min) };
+        if !self.eat('}') {
+            anyhow::bail!("unclosed {{");
+        }
+        if max.is_some_and(|max| max < min) {
+            anyhow::bail!("{{{},{}}} is out of order", min, max.unwrap_or_default());
+        }
+        if max.unwrap_or(min) > MAX_REPEAT {
+            anyhow::bail!("repetition counts above {} are not supported", MAX_REPEAT);
+        }
+        Ok((min, max))
+    }
+
+    fn number(&mut self) -> Result<Option<u32>> {
+        let mut digits = String::new();
+        while let Some(c) = self.chars.next_if(char::is_ascii_digit) {
+            digits.push(c);
+        }
+        if digits.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(digits.parse()?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    AnyButNewline,
+    Class(Class),
+    Assert(Assertion),
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// How many instructions `compile` emits for `ast`, saturating rather than overflowing
+fn compiled_len(ast: &Ast) -> usize {
+    match ast {
+        Ast::Empty => 0,
+        Ast::Literal(_) | Ast::AnyButNewline | Ast::Class(_) | Ast::Assert(_) => 1,
+        Ast::Concat(items) => items.iter().fold(0, |len, item| len.saturating_add(compiled_len(item))),
+        Ast::Alternate(branches) => branches
+            .iter()
+            .fold(2 * (branches.len() - 1), |len, branch| len.saturating_add(compiled_len(branch))),
+        Ast::Repeat { ast, min, max } => {
+            let inner = compiled_len(ast);
+            let optional = match max {
+                None => inner.saturating_add(2),
+                Some(max) => inner.saturating_add(1).saturating_mul((max - min) as usize),
+            };
+            inner.saturating_mul(*min as usize).saturating_add(optional)
+        }
+    }
+}
+
+fn compile(ast: &Ast, program: &mut Vec<Inst>) {
+    match ast {
+        Ast::Empty => {}
+        Ast::Literal(c) => program.push(Inst::Char(*c)),
+        Ast::AnyButNewline => program.push(Inst::AnyButNewline),
+        Ast::Class(class) => program.push(Inst::Class(class.clone())),
+        Ast::Assert(assertion) => program.push(Inst::Assert(*assertion)),
+        Ast::Concat(items) => items.iter().for_each(|item| compile(item, program)),
+        Ast::Alternate(branches) => {
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 < branches.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(branch, program);
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    let next = program.len();
+                    program[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(branch, program);
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Ast::Repeat { ast, min, max } => {
+            for _ in 0..*min {
+                compile(ast, program);
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(ast, program);
+                    program.push(Inst::Jump(split));
+                    let end = program.len();
+                    program[split] = Inst::Split(split + 1, end);
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(ast, program);
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A compiled `--done-regex`, (de)serialized as its source so config files are validated too
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern {
+    source: String,
+    program: Vec<Inst>,
+    case_insensitive: bool,
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        Pattern::new(&source)
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> String {
+        pattern.source
+    }
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let (case_insensitive, rest) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser { chars: rest.chars().peekable(), depth: 0 };
+        let mut ast = parser.alternation()?;
+        if parser.chars.next().is_some() {
+            anyhow::bail!("unmatched )");
+        }
+        if case_insensitive {
+            ast = fold_case(ast);
+        }
+        if compiled_len(&ast) >= MAX_PROGRAM_LEN {
+            anyhow::bail!("pattern is too large (nested repetitions multiply; keep their product under {})", MAX_PROGRAM_LEN);
+        }
+        let mut program = Vec::new();
+        compile(&ast, &mut program);
+        program.push(Inst::Match);
+        Ok(Pattern { source: pattern.to_string(), program, case_insensitive })
+    }
+
+    /// Whether the pattern matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = if self.case_insensitive { text.chars().map(fold_char).collect() } else { text.chars().collect() };
+        let mut current: Vec<usize> = Vec::new();
+        let mut next: Vec<usize> = Vec::new();
+        let mut seen = vec![usize::MAX; self.program.len()];
+        let mut stack = Vec::new();
+        for pos in 0..=chars.len() {
+            // A new attempt starts at every position, so the match can begin anywhere
+            if self.add_thread(&mut current, &mut seen, &mut stack, pos, 0, &chars) {
+                return true;
+            }
+            let Some(&c) = chars.get(pos) else { break };
+            next.clear();
+            for &pc in &current {
+                let advances = match &self.program[pc] {
+                    Inst::Char(expected) => c == *expected,
+                    Inst::AnyButNewline => c != '\n',
+                    Inst::Class(class) => class.contains(c),
+                    _ => false,
+                };
+                if advances && self.add_thread(&mut next, &mut seen, &mut stack, pos + 1, pc + 1, &chars) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    /// Follow `pc`'s splits, jumps, and assertions at `pos`; true once `Match` is reached.
+    /// The chains can be tens of thousands of instructions long, so they're walked with
+    /// `stack` rather than by recursion
+    fn add_thread(
+        &self,
+        threads: &mut Vec<usize>,
+        seen: &mut [usize],
+        stack: &mut Vec<usize>,
+        pos: usize,
+        pc: usize,
+        chars: &[char],
+    ) -> bool {
+        stack.clear();
+        stack.push(pc);
+        while let Some(pc) = stack.pop() {
+            if seen[pc] == pos {
+                continue;
+            }
+            seen[pc] = pos;
+            match &self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(*to),
+                Inst::Split(a, b) => {
+                    stack.push(*b);
+                    stack.push(*a);
+                }
+                Inst::Assert(assertion) => {
+                    let before = pos.checked_sub(1).map(|i| chars[i]);
+                    let after = chars.get(pos).copied();
+                    let boundary = before.is_some_and(is_word) != after.is_some_and(is_word);
+                    let holds = match assertion {
+                        Assertion::LineStart => before.is_none_or(|c| c == '\n'),
+                        Assertion::LineEnd => after.is_none_or(|c| c == '\n' || c == '\r'),
+                        Assertion::WordBoundary => boundary,
+                        Assertion::NotWordBoundary => !boundary,
+                    };
+                    if holds {
+                        stack.push(pc + 1);
+                    }
+                }
+                _ => threads.push(pc),
+            }
+        }
+        false
+    }
+}
+
+fn fold_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Lowercase the pattern's literals and give its classes their other-case letters
+fn fold_case(ast: Ast) -> Ast {
+    match ast {
+        Ast::Literal(c) => Ast::Literal(fold_char(c)),
+        Ast::Class(Class { ranges, negated }) => {
+            let mut folded = ranges.clone();
+            for &(lo, hi) in &ranges {
+                for c in lo..=hi {
+                    let lower = fold_char(c);
+                    if lower != c {
+                        folded.push((lower, lower));
+                    }
+                }
+            }
+            Ast::Class(Class { ranges: folded, negated })
+        }
+        Ast::Concat(items) => Ast::Concat(items.into_iter().map(fold_case).collect()),
+        Ast::Alternate(branches) => Ast::Alternate(branches.into_iter().map(fold_case).collect()),
+        Ast::Repeat { ast, min, max } => Ast::Repeat { ast: Box::new(fold_case(*ast)), min, max },
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_pattern_matches_anywhere() {
+        assert!(matches("ALL_DONE", "Everything looks good — ALL_DONE, ship it."));
+        assert!(!matches("ALL_DONE", "all_done"));
+        assert!(matches("(?i)all_done", "Looks good. ALL_DONE"));
+        assert!(matches("", "anything"));
+    }
+
+    #[test]
+    fn test_pattern_syntax() {
+        assert!(matches(r"\bALL_DONE\b", "so: ALL_DONE."));
+        assert!(!matches(r"\bALL_DONE\b", "NOT_ALL_DONE_YET"));
+        assert!(matches("^(LGTM|ALL_DONE)$", "Reviewed the diff.\nLGTM\n"));
+        assert!(!matches("^(LGTM|ALL_DONE)$", "LGTM, but fix the test"));
+        assert!(matches(r"score: \d{2,3}/100", "score: 95/100"));
+        assert!(!matches(r"score: \d{2,3}/100", "score: 5/100"));
+        assert!(matches("a.c", "abc"));
+        assert!(!matches("a.c", "a\nc"));
+        assert!(matches("[^ ]+_DONE", "TASK_DONE"));
+        assert!(matches("(?i)[a-c]+x", "ABCX"));
+        assert!(matches("colou?r", "color"));
+        assert!(matches("(?:ab)+?c", "ababc"));
+        assert!(matches("x(a+)?y", "xy"));
+        assert!(matches(r"\.\*", "a.*b"));
+        assert!(matches("[a-]", "-"));
+    }
+
+    #[test]
+    fn test_pattern_is_linear_on_pathological_input() {
+        let text = "a".repeat(5000);
+        assert!(!matches("(a*)*b", &text));
+        assert!(!matches("(a|aa)+$b", &text));
+    }
+
+    #[test]
+    fn test_pattern_rejects_unsupported_syntax() {
+        for bad in ["(abc", "abc)", "[abc", "*a", r"(a)\1", "(?=a)", "a{3,1}", "a{5000}", r"\q", "[z-a]"] {
+            assert!(Pattern::new(bad).is_err(), "{} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn test_pattern_follows_deep_split_chains() {
+        let pattern = Pattern::new("(?:(?:a?){220}){220}c").unwrap();
+        assert!(pattern.is_match("aaac"));
+        assert!(!pattern.is_match("aaab"));
+    }
+
+    #[test]
+    fn test_pattern_rejects_deep_nesting() {
+        assert!(Pattern::new(&format!("{}a{}", "(".repeat(10_000), ")".repeat(10_000))).is_err());
+        assert!(Pattern::new(&format!("{}a{}", "(".repeat(50), ")".repeat(50))).is_ok());
+        assert!(Pattern::new(&format!("a{}", "*".repeat(10_000))).is_err());
+        assert!(Pattern::new("a+*").is_err());
+    }
+
+    #[test]
+    fn test_pattern_rejects_oversized_programs() {
+        assert!(Pattern::new("((a{1000}){1000}){1000}").is_err());
+        assert!(Pattern::new("(a{1000}){1000}").is_err());
+        assert!(Pattern::new("(a{10}){10}").is_ok());
+    }
+}
@@ -0,0 +1,234 @@
+//! `leonard prompts`: re-derive every prompt a run would send, from canned agent outputs
+//!
+//! Replays a relay without spawning agents: each canned driver output yields the next
+//! navigator prompt, and each canned navigator output yields the next driver prompt,
+//! using the same builders and flags as `run_session`. Useful for diffing prompt changes.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::done::done_signal;
+use crate::output::Role;
+use crate::{
+    build_driver_feedback_prompt, initial_driver_prompt, maybe_color, navigator_prompt_for_turn,
+    prepare_forward, strip_ansi, Args, EmptyNavigatorAction, PromptOptions, NO_FEEDBACK_NUDGE,
+};
+
+/// Stands in for the per-run fence nonce so output is identical across runs
+const FIXED_NONCE: &str = "0000000000000000";
+
+/// One agent reply, in relay order
+#[derive(Deserialize, Debug)]
+struct CannedOutput {
+    role: Role,
+    text: String,
+}
+
+/// A bare list of outputs, or an object with an `entries` list (e.g. a --transcript file)
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum CannedFile {
+    Entries(Vec<serde_json::Value>),
+    Transcript { entries: Vec<serde_json::Value> },
+}
+
+impl CannedFile {
+    /// The agent replies, skipping transcript error entries
+    fn into_outputs(self) -> Result<Vec<CannedOutput>> {
+        let (CannedFile::Entries(entries) | CannedFile::Transcript { entries }) = self;
+        entries
+            .into_iter()
+            .filter(|entry| entry.get("kind").and_then(|k| k.as_str()) != Some("error"))
+            .enumerate()
+            .map(|(i, entry)| {
+                serde_json::from_value(entry).with_context(|| format!("entry {} needs a role and text", i + 1))
+            })
+            .collect()
+    }
+}
+
+/// A prompt Leonard would have sent
+#[derive(Debug)]
+struct DerivedPrompt {
+    role: Role,
+    turn: usize,
+    text: String,
+}
+
+fn read_outputs(path: &Path) -> Result<Vec<CannedOutput>> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let file: CannedFile =
+        serde_json::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))?;
+    file.into_outputs().with_context(|| format!("invalid output in {}", path.display()))
+}
+
+/// Walk the relay over `outputs`, collecting each prompt in the order it would be sent
+fn derive_prompts(
+    args: &Args,
+    task: Option<&str>,
+    context: Option<&str>,
+    outputs: &[CannedOutput],
+) -> Result<Vec<DerivedPrompt>> {
+    let mut opts = PromptOptions::from_args(args);
+    if opts.fence_nonce.is_some() {
+        opts.fence_nonce = Some(FIXED_NONCE.to_string());
+    }
+
+    let mut prompts = vec![DerivedPrompt {
+        role: Role::Driver,
+        turn: 0,
+        text: initial_driver_prompt(args, task, context),
+    }];
+    let mut turn = 0;
+    let mut expected = Role::Driver;
+
+    for (i, output) in outputs.iter().enumerate() {
+        if output.role != expected {
+            anyhow::bail!(
+                "entry {}: expected {} output, found {} (outputs must alternate, starting with the driver)",
+                i + 1,
+                expected.name(),
+                output.role.name()
+            );
+        }
+        let text = if args.strip_ansi { strip_ansi(&output.text) } else { output.text.clone() };
+
+        match output.role {
+            Role::Driver => {
+                if args.done_from.includes(Role::Driver) && done_signal(args, &text).is_some() {
+                    break;
+                }
+                let prompt = navigator_prompt_for_turn(args, &mut opts, task, context, &text, turn, turn > 0 || args.resumes_session(Role::Navigator));
+                prompts.push(DerivedPrompt { role: Role::Navigator, turn, text: prompt });
+                expected = Role::Navigator;
+            }
+            Role::Navigator => {
+                if args.done_from.includes(Role::Navigator) && done_signal(args, &text).is_some() {
+                    break;
+                }
+                let feedback = if text.trim().is_empty() {
+                    if args.on_empty_navigator == EmptyNavigatorAction::Stop {
+                        break;
+                    }
+                    NO_FEEDBACK_NUDGE.to_string()
+                } else {
+                    build_driver_feedback_prompt(&prepare_forward(args, &text, turn), &opts)
+                };
+                turn += 1;
+                prompts.push(DerivedPrompt { role: Role::Driver, turn, text: feedback });
+                expected = Role::Driver;
+
+                if args.max_turns > 0 && turn >= args.max_turns {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(prompts)
+}
+
+/// Print (or write to `out_dir`) every prompt derived from the canned outputs in `outputs_path`
+pub fn run_prompts(
+    args: &Args,
+    task: Option<&str>,
+    context: Option<&str>,
+    outputs_path: &Path,
+    out_dir: Option<&Path>,
+) -> Result<()> {
+    let outputs = read_outputs(outputs_path)?;
+    let prompts = derive_prompts(args, task, context, &outputs)?;
+
+    if let Some(dir) = out_dir {
+        std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        for (i, prompt) in prompts.iter().enumerate() {
+            let path = dir.join(format!("{:02}-{}-turn{}.txt", i + 1, prompt.role.name(), prompt.turn));
+            std::fs::write(&path, &prompt.text).with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        println!("wrote {} prompt(s) to {}", prompts.len(), dir.display());
+        return Ok(());
+    }
+
+    let theme = args.theme();
+    let colors = args.colors();
+    for prompt in &prompts {
+        let header = format!("=== {} PROMPT (turn {}) ===", prompt.role.name().to_uppercase(), prompt.turn);
+        println!("{}", maybe_color(colors, header, |s| s.color(theme.header(prompt.role)).bold()));
+        println!("{}", prompt.text);
+        println!();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn outputs(json: &str) -> Vec<CannedOutput> {
+        serde_json::from_str::<CannedFile>(json).unwrap().into_outputs().unwrap()
+    }
+
+    fn roles(prompts: &[DerivedPrompt]) -> Vec<(Role, usize)> {
+        prompts.iter().map(|p| (p.role, p.turn)).collect()
+    }
+
+    #[test]
+    fn test_derive_prompts_alternates_roles() {
+        let args = Args::parse_from(["leonard"]);
+        let canned = outputs(
+            r#"[{"role": "driver", "text": "plan"}, {"role": "navigator", "text": "fix the test"}, {"role": "driver", "text": "fixed"}]"#,
+        );
+        let prompts = derive_prompts(&args, Some("the task"), None, &canned).unwrap();
+        assert_eq!(
+            roles(&prompts),
+            vec![(Role::Driver, 0), (Role::Navigator, 0), (Role::Driver, 1), (Role::Navigator, 1)]
+        );
+        assert!(prompts[0].text.contains("the task"));
+        assert!(prompts[1].text.contains("plan"));
+        assert_eq!(prompts[2].text, "fix the test");
+    }
+
+    #[test]
+    fn test_derive_prompts_stops_at_all_done_and_max_turns() {
+        let args = Args::parse_from(["leonard"]);
+        let canned = outputs(r#"{"entries": [{"role": "driver", "text": "done"}, {"role": "navigator", "text": "ALL_DONE"}]}"#);
+        assert_eq!(derive_prompts(&args, Some("t"), None, &canned).unwrap().len(), 2);
+
+        let args = Args::parse_from(["leonard", "--max-turns", "1"]);
+        let canned = outputs(
+            r#"[{"role": "driver", "text": "a"}, {"role": "navigator", "text": "b"}, {"role": "driver", "text": "c"}]"#,
+        );
+        assert_eq!(roles(&derive_prompts(&args, Some("t"), None, &canned).unwrap()).last(), Some(&(Role::Driver, 1)));
+    }
+
+    #[test]
+    fn test_derive_prompts_fence_is_deterministic() {
+        let args = Args::parse_from(["leonard", "--fence-agent-output"]);
+        let canned = outputs(r#"[{"role": "driver", "text": "x"}]"#);
+        let prompts = derive_prompts(&args, Some("t"), None, &canned).unwrap();
+        assert!(prompts[1].text.contains(FIXED_NONCE));
+    }
+
+    #[test]
+    fn test_transcript_entries_are_accepted() {
+        let canned = outputs(
+            r#"{"entries": [
+                {"kind": "message", "turn": 0, "role": "driver", "timestamp": "t", "text": "plan"},
+                {"kind": "error", "turn": 0, "role": "navigator", "timestamp": "t", "message": "navigator exited"}
+            ]}"#,
+        );
+        assert_eq!(canned.len(), 1);
+        assert_eq!(canned[0].text, "plan");
+    }
+
+    #[test]
+    fn test_derive_prompts_rejects_out_of_order_outputs() {
+        let args = Args::parse_from(["leonard"]);
+        let canned = outputs(r#"[{"role": "navigator", "text": "x"}]"#);
+        let err = derive_prompts(&args, Some("t"), None, &canned).unwrap_err().to_string();
+        assert!(err.contains("expected driver output"));
+    }
+}
@@ -0,0 +1,112 @@
+//! `--status-file`: a tiny JSON liveness/progress record for supervisors
+//!
+//! Overwritten on start, after every agent reply, and on exit. Each write goes to a
+//! temp file that is then renamed over the target, so a poller never sees half a file.
+
+use serde::Serialize;
+use std::cell::Cell;
+use std::path::PathBuf;
+
+use crate::output::Role;
+use crate::{log_line, timestamp};
+
+/// Where the run is; `done` and `failed` are terminal
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    Starting,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Serialize)]
+struct StatusRecord {
+    pid: u32,
+    turn: usize,
+    state: RunState,
+    last_update_ts: String,
+    last_role: Option<Role>,
+}
+
+/// Keeps the status file (if any) current; a no-op without a path
+pub struct StatusFile {
+    path: Option<PathBuf>,
+    turn: Cell<usize>,
+    last_role: Cell<Option<Role>>,
+    write_failed: Cell<bool>,
+}
+
+impl StatusFile {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let status = StatusFile { path, turn: Cell::new(0), last_role: Cell::new(None), write_failed: Cell::new(false) };
+        status.write(RunState::Starting);
+        status
+    }
+
+    /// `role` just finished its reply for `turn`
+    pub fn replied(&self, turn: usize, role: Role) {
+        self.turn.set(turn);
+        self.last_role.set(Some(role));
+        self.write(RunState::Running);
+    }
+
+    /// Record the run's terminal state
+    pub fn finish(&self, succeeded: bool) {
+        self.write(if succeeded { RunState::Done } else { RunState::Failed });
+    }
+
+    fn write(&self, state: RunState) {
+        let Some(ref path) = self.path else { return };
+        let record = StatusRecord {
+            pid: std::process::id(),
+            turn: self.turn.get(),
+            state,
+            last_update_ts: timestamp(),
+            last_role: self.last_role.get(),
+        };
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let result = serde_json::to_string(&record)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&tmp, json + "\n"))
+            .and_then(|_| std::fs::rename(&tmp, path));
+        if let Err(e) = result {
+            if !self.write_failed.replace(true) {
+                log_line("system", &format!("warning: failed to write status file {}: {}", path.display(), e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &std::path::Path) -> serde_json::Value {
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_status_file_tracks_progress_and_exit() {
+        let path = std::env::temp_dir().join(format!("leonard-status-test-{}.json", std::process::id()));
+        let status = StatusFile::new(Some(path.clone()));
+        let record = read(&path);
+        assert_eq!(record["state"], "starting");
+        assert_eq!(record["pid"], std::process::id());
+        assert!(record["last_role"].is_null());
+
+        status.replied(2, Role::Navigator);
+        let record = read(&path);
+        assert_eq!(record["state"], "running");
+        assert_eq!(record["turn"], 2);
+        assert_eq!(record["last_role"], "navigator");
+
+        status.finish(false);
+        let record = read(&path);
+        assert_eq!(record["state"], "failed");
+        assert_eq!(record["turn"], 2);
+        assert!(!record["last_update_ts"].as_str().unwrap().is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
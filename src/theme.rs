@@ -0,0 +1,119 @@
+//! `--theme`: the colors each agent's output is shown in
+//!
+//! A theme spec is a comma-separated list of `key=color`. The keys are `driver`, `navigator`
+//! (both header and body), or `driver-header`, `driver-body`, `navigator-header`,
+//! `navigator-body`. The colors are the terminal's named colors (`blue`, `bright-yellow`)
+//! or hex (`#ffa500`, `#fa0`). Unset keys keep the defaults: driver cyan, navigator magenta.
+
+use colored::Color;
+
+use crate::output::Role;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RoleColors {
+    /// Turn headers and dialogue labels
+    header: Color,
+    /// Streamed messages and --quiet summaries
+    body: Color,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    driver: RoleColors,
+    navigator: RoleColors,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            driver: RoleColors { header: Color::Cyan, body: Color::Cyan },
+            navigator: RoleColors { header: Color::Magenta, body: Color::Magenta },
+        }
+    }
+}
+
+impl Theme {
+    /// The default theme with `spec`'s colors applied
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut theme = Theme::default();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (key, value) =
+                entry.split_once('=').ok_or_else(|| format!("expected key=color, got '{}'", entry))?;
+            let color = parse_color(value.trim())?;
+            let (role, part) = match key.trim().split_once('-') {
+                Some((role, part)) => (role, Some(part)),
+                None => (key.trim(), None),
+            };
+            let colors = match role {
+                "driver" => &mut theme.driver,
+                "navigator" => &mut theme.navigator,
+                _ => return Err(format!("unknown theme key '{}' (expected driver or navigator, optionally with -header or -body)", key.trim())),
+            };
+            match part {
+                None => *colors = RoleColors { header: color, body: color },
+                Some("header") => colors.header = color,
+                Some("body") => colors.body = color,
+                Some(_) => return Err(format!("unknown theme key '{}' (expected -header or -body)", key.trim())),
+            }
+        }
+        Ok(theme)
+    }
+
+    fn colors(&self, role: Role) -> RoleColors {
+        match role {
+            Role::Driver => self.driver,
+            Role::Navigator => self.navigator,
+        }
+    }
+
+    pub fn header(&self, role: Role) -> Color {
+        self.colors(role).header
+    }
+
+    pub fn body(&self, role: Role) -> Color {
+        self.colors(role).body
+    }
+}
+
+/// A named color (`bright-red`, `bright_red`, or `bright red`) or hex (`#rrggbb`, `#rgb`)
+fn parse_color(s: &str) -> Result<Color, String> {
+    if let Some(hex) = s.strip_prefix('#') {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("invalid hex color '{}'", s))?;
+        return match digits[..] {
+            [r, g, b] => Ok(Color::TrueColor { r: r * 17, g: g * 17, b: b * 17 }),
+            [r1, r2, g1, g2, b1, b2] => Ok(Color::TrueColor { r: r1 * 16 + r2, g: g1 * 16 + g2, b: b1 * 16 + b2 }),
+            _ => Err(format!("invalid hex color '{}' (expected #rgb or #rrggbb)", s)),
+        };
+    }
+    s.replace(['-', '_'], " ").parse().map_err(|()| format!("unknown color '{}'", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Theme::parse() tests
+    #[test]
+    fn test_theme_parse() {
+        assert_eq!(Theme::parse("").unwrap(), Theme::default());
+
+        let theme = Theme::parse("driver=blue, navigator-body=#ffa500, navigator-header=Bright_Yellow").unwrap();
+        assert_eq!((theme.header(Role::Driver), theme.body(Role::Driver)), (Color::Blue, Color::Blue));
+        assert_eq!(theme.header(Role::Navigator), Color::BrightYellow);
+        assert_eq!(theme.body(Role::Navigator), Color::TrueColor { r: 255, g: 165, b: 0 });
+
+        assert_eq!(Theme::parse("driver-header=#f80").unwrap().header(Role::Driver), Color::TrueColor { r: 255, g: 136, b: 0 });
+        assert_eq!(Theme::parse("driver-header=#f80").unwrap().body(Role::Driver), Color::Cyan);
+    }
+
+    #[test]
+    fn test_theme_parse_rejects_bad_specs() {
+        for bad in ["driver", "critic=red", "driver-footer=red", "driver=orange", "driver=#ffa50", "driver=#ggg"] {
+            assert!(Theme::parse(bad).is_err(), "{} should be rejected", bad);
+        }
+    }
+}
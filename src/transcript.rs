@@ -0,0 +1,347 @@
+//! `--transcript`: a JSON record of every agent reply and failure in a run
+//!
+//! The file is rewritten after every entry, so a run that fails or is interrupted
+//! still leaves a complete transcript up to that point. The `{"entries": [...]}`
+//! shape is also what `leonard prompts --outputs` and `--resume-transcript` accept.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::markdown;
+use crate::output::Role;
+use crate::{log_line, timestamp, DeadlineReached};
+
+/// How many trailing stderr lines of a failed agent to keep
+pub const STDERR_TAIL_LINES: usize = 20;
+
+/// An agent process that exited unsuccessfully, with the end of its stderr
+#[derive(Debug)]
+pub struct AgentExit {
+    pub agent: &'static str,
+    pub status: std::process::ExitStatus,
+    pub stderr_tail: Vec<String>,
+    /// Stdout lines that weren't stream events, e.g. a plain-text fatal error
+    pub unparsed_stdout: Vec<String>,
+}
+
+impl AgentExit {
+    pub fn new(agent: &'static str, status: std::process::ExitStatus, stderr_lines: &[String]) -> Self {
+        AgentExit { agent, status, stderr_tail: tail(stderr_lines), unparsed_stdout: Vec::new() }
+    }
+
+    /// Keep the last of `lines`, the agent's unparseable stdout, for the error message
+    pub fn with_unparsed_stdout(mut self, lines: &[String]) -> Self {
+        self.unparsed_stdout = tail(lines);
+        self
+    }
+}
+
+fn tail(lines: &[String]) -> Vec<String> {
+    lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].to_vec()
+}
+
+impl std::fmt::Display for AgentExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} exited with status: {}", self.agent, self.status)?;
+        if !self.unparsed_stdout.is_empty() {
+            write!(f, "; it printed: {}", self.unparsed_stdout.join("\n"))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AgentExit {}
+
+/// Something an agent did while producing a reply
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Activity {
+    /// A reasoning summary
+    Reasoning { text: String },
+    /// A tool call, with the summary of its result once one arrives
+    ToolCall {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        result: Option<String>,
+    },
+    /// A shell command the agent ran
+    Command { command: String, exit_code: i32, summary: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TranscriptEntry {
+    /// An agent's reply, as forwarded (before truncation), and the tool activity behind it
+    Message {
+        turn: usize,
+        role: Role,
+        timestamp: String,
+        text: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        activity: Vec<Activity>,
+    },
+    /// An agent run that failed; the relay stopped here
+    Error {
+        turn: usize,
+        role: Role,
+        timestamp: String,
+        message: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        stderr_tail: Vec<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct TranscriptFile<'a> {
+    /// The run's --meta entries
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: &'a BTreeMap<String, String>,
+    entries: &'a [TranscriptEntry],
+}
+
+/// Collects entries and keeps the transcript file (and --markdown-transcript, if any) up to date
+pub struct Transcript {
+    path: Option<PathBuf>,
+    markdown_path: Option<PathBuf>,
+    metadata: BTreeMap<String, String>,
+    entries: Vec<TranscriptEntry>,
+    write_failed: bool,
+}
+
+impl Transcript {
+    pub fn new(path: Option<PathBuf>, metadata: Vec<(String, String)>) -> Self {
+        Transcript {
+            path,
+            markdown_path: None,
+            metadata: metadata.into_iter().collect(),
+            entries: Vec::new(),
+            write_failed: false,
+        }
+    }
+
+    /// Also keep a Markdown rendering of the session at `path` (--markdown-transcript)
+    pub fn with_markdown(mut self, path: Option<PathBuf>) -> Self {
+        self.markdown_path = path;
+        self
+    }
+
+    /// Start from the entries of an earlier run (--resume-transcript)
+    pub fn seed(&mut self, entries: Vec<TranscriptEntry>) {
+        self.entries.extend(entries);
+        self.write();
+    }
+
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.entries
+    }
+
+    pub fn into_entries(self) -> Vec<TranscriptEntry> {
+        self.entries
+    }
+
+    pub fn message(&mut self, turn: usize, role: Role, text: &str, activity: Vec<Activity>) {
+        self.push(TranscriptEntry::Message { turn, role, timestamp: timestamp(), text: text.to_string(), activity });
+    }
+
+    pub fn error(&mut self, turn: usize, role: Role, err: &anyhow::Error) {
+        let stderr_tail = err.downcast_ref::<AgentExit>().map(|e| e.stderr_tail.clone()).unwrap_or_default();
+        self.push(TranscriptEntry::Error {
+            turn,
+            role,
+            timestamp: timestamp(),
+            message: format!("{:#}", err),
+            stderr_tail,
+        });
+    }
+
+    /// Record `result` as an error entry if it failed, then pass it through. A turn cut
+    /// short by --deadline isn't a failure, so it isn't recorded
+    pub fn check<T>(&mut self, turn: usize, role: Role, result: anyhow::Result<T>) -> anyhow::Result<T> {
+        if let Err(ref e) = result {
+            if e.downcast_ref::<DeadlineReached>().is_some() {
+                return result;
+            }
+            self.error(turn, role, e);
+        }
+        result
+    }
+
+    fn push(&mut self, entry: TranscriptEntry) {
+        self.entries.push(entry);
+        self.write();
+    }
+
+    /// Rewrite the whole file(s); a failure is logged once and doesn't stop the relay
+    fn write(&mut self) {
+        if let Some(ref path) = self.path {
+            let result = serde_json::to_string_pretty(&TranscriptFile { metadata: &self.metadata, entries: &self.entries })
+                .map_err(std::io::Error::from)
+                .and_then(|json| std::fs::write(path, json + "\n"));
+            if let Err(e) = result {
+                self.warn_once(path.clone(), e);
+            }
+        }
+        if let Some(ref path) = self.markdown_path {
+            if let Err(e) = std::fs::write(path, markdown::render_transcript(&self.entries)) {
+                self.warn_once(path.clone(), e);
+            }
+        }
+    }
+
+    fn warn_once(&mut self, path: PathBuf, e: std::io::Error) {
+        if !self.write_failed {
+            log_line("system", &format!("warning: failed to write transcript {}: {}", path.display(), e));
+            self.write_failed = true;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SavedTranscript {
+    entries: Vec<TranscriptEntry>,
+}
+
+/// Where a --resume-transcript run picks up: the last driver reply at or before --from-turn
+#[derive(Debug)]
+pub struct ResumePoint {
+    pub turn: usize,
+    pub driver_output: String,
+    /// The saved entries up to and including that reply
+    pub entries: Vec<TranscriptEntry>,
+}
+
+impl ResumePoint {
+    pub fn load(path: &Path, from_turn: Option<usize>) -> Result<Self> {
+        let content = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let saved: SavedTranscript =
+            serde_json::from_str(&content).with_context(|| format!("failed to parse transcript {}", path.display()))?;
+        Self::from_entries(saved.entries, from_turn).with_context(|| format!("can't resume from {}", path.display()))
+    }
+
+    fn from_entries(mut entries: Vec<TranscriptEntry>, from_turn: Option<usize>) -> Result<Self> {
+        let found = entries.iter().enumerate().rev().find_map(|(i, entry)| match entry {
+            TranscriptEntry::Message { turn, role: Role::Driver, text, .. } if from_turn.is_none_or(|n| *turn <= n) => {
+                Some((i, *turn, text.clone()))
+            }
+            _ => None,
+        });
+        let Some((index, turn, driver_output)) = found else {
+            match from_turn {
+                Some(n) => anyhow::bail!("no driver reply at or before turn {}", n),
+                None => anyhow::bail!("no driver reply to resume from"),
+            }
+        };
+        entries.truncate(index + 1);
+        Ok(ResumePoint { turn, driver_output, entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn failed_status() -> std::process::ExitStatus {
+        use std::os::unix::process::ExitStatusExt;
+        std::process::ExitStatus::from_raw(1 << 8)
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_agent_exit_keeps_stderr_tail() {
+        let lines: Vec<String> = (0..30).map(|i| format!("line {}", i)).collect();
+        let exit = AgentExit::new("driver", failed_status(), &lines);
+        assert_eq!(exit.stderr_tail.len(), STDERR_TAIL_LINES);
+        assert_eq!(exit.stderr_tail[0], "line 10");
+        assert_eq!(exit.to_string(), "driver exited with status: exit status: 1");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_agent_exit_reports_unparsed_stdout() {
+        let exit = AgentExit::new("driver", failed_status(), &[])
+            .with_unparsed_stdout(&["Error: Invalid API key".to_string()]);
+        assert_eq!(exit.to_string(), "driver exited with status: exit status: 1; it printed: Error: Invalid API key");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_check_records_error_with_stderr() {
+        let mut transcript = Transcript::new(None, Vec::new());
+        let err: anyhow::Result<()> =
+            Err(AgentExit::new("navigator", failed_status(), &["auth failed".to_string()]).into());
+        assert!(transcript.check(2, Role::Navigator, err).is_err());
+        match &transcript.entries[0] {
+            TranscriptEntry::Error { turn, role, stderr_tail, .. } => {
+                assert_eq!(*turn, 2);
+                assert_eq!(*role, Role::Navigator);
+                assert_eq!(stderr_tail, &vec!["auth failed".to_string()]);
+            }
+            other => panic!("expected an error entry, got {:?}", other),
+        }
+    }
+
+    fn message(turn: usize, role: Role, text: &str) -> TranscriptEntry {
+        TranscriptEntry::Message { turn, role, timestamp: String::new(), text: text.to_string(), activity: Vec::new() }
+    }
+
+    #[test]
+    fn test_resume_point_truncates_to_driver_reply() {
+        let entries = vec![
+            message(0, Role::Driver, "plan"),
+            message(0, Role::Navigator, "add tests"),
+            message(1, Role::Driver, "added tests"),
+            message(1, Role::Navigator, "rename it"),
+            message(2, Role::Driver, "renamed badly"),
+        ];
+
+        let point = ResumePoint::from_entries(entries.clone(), Some(1)).unwrap();
+        assert_eq!(point.turn, 1);
+        assert_eq!(point.driver_output, "added tests");
+        assert_eq!(point.entries, entries[..3]);
+
+        let point = ResumePoint::from_entries(entries.clone(), None).unwrap();
+        assert_eq!((point.turn, point.entries.len()), (2, 5));
+
+        assert!(ResumePoint::from_entries(entries[1..2].to_vec(), None).is_err());
+    }
+
+    #[test]
+    fn test_markdown_transcript_is_written() {
+        let path = std::env::temp_dir().join(format!("leonard-transcript-test-{}.md", std::process::id()));
+        let mut transcript = Transcript::new(None, Vec::new()).with_markdown(Some(path.clone()));
+        transcript.message(0, Role::Driver, "plan", Vec::new());
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains("## Driver (turn 0)"));
+        assert!(written.ends_with("plan\n"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_transcript_file_is_rewritten_per_entry() {
+        let path = std::env::temp_dir().join(format!("leonard-transcript-test-{}.json", std::process::id()));
+        let mut transcript = Transcript::new(Some(path.clone()), vec![("ticket".to_string(), "ABC-1".to_string())]);
+        let activity = vec![
+            Activity::ToolCall { name: "Read".to_string(), result: Some("12 lines".to_string()) },
+            Activity::Command { command: "cargo test".to_string(), exit_code: 0, summary: "ok".to_string() },
+        ];
+        transcript.message(0, Role::Driver, "plan", activity);
+        transcript.error(0, Role::Navigator, &anyhow::anyhow!("failed to spawn codex"));
+
+        let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["metadata"]["ticket"], "ABC-1");
+        let entries = written["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["kind"], "message");
+        assert_eq!(entries[0]["role"], "driver");
+        assert_eq!(entries[0]["text"], "plan");
+        assert_eq!(entries[0]["activity"][0], serde_json::json!({"kind": "tool_call", "name": "Read", "result": "12 lines"}));
+        assert_eq!(entries[0]["activity"][1]["exit_code"], 0);
+        assert_eq!(entries[1]["kind"], "error");
+        assert_eq!(entries[1]["message"], "failed to spawn codex");
+        assert!(entries[1].get("stderr_tail").is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,127 @@
+//! Tally of agent stream events Leonard doesn't recognise
+//!
+//! The `#[serde(other)] Unknown` variants keep parsing robust, but they also hide
+//! upstream schema drift. The parsers report each unknown here; the run ends with a
+//! one-line count, and `--debug-unknown` saves one raw sample per unknown type.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Top-level claude event types Leonard deliberately ignores
+const IGNORED_CLAUDE_EVENTS: &[&str] = &["system"];
+
+/// Top-level codex event types Leonard deliberately ignores
+const IGNORED_CODEX_EVENTS: &[&str] = &["turn.started", "item.updated"];
+
+/// Which parser met the unknown value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UnknownKind {
+    ClaudeEvent,
+    ClaudeBlock,
+    CodexEvent,
+    CodexItem,
+}
+
+impl UnknownKind {
+    fn label(self) -> &'static str {
+        match self {
+            UnknownKind::ClaudeEvent => "claude event",
+            UnknownKind::ClaudeBlock => "claude content block",
+            UnknownKind::CodexEvent => "codex event",
+            UnknownKind::CodexItem => "codex item",
+        }
+    }
+
+    fn ignored(self) -> &'static [&'static str] {
+        match self {
+            UnknownKind::ClaudeEvent => IGNORED_CLAUDE_EVENTS,
+            UnknownKind::CodexEvent => IGNORED_CODEX_EVENTS,
+            UnknownKind::ClaudeBlock | UnknownKind::CodexItem => &[],
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct UnknownEvents {
+    counts: BTreeMap<UnknownKind, usize>,
+    /// First raw value seen per `<kind>: <type>`
+    samples: BTreeMap<String, serde_json::Value>,
+}
+
+impl UnknownEvents {
+    fn record(&mut self, kind: UnknownKind, raw: serde_json::Value) {
+        let type_name = raw.get("type").and_then(|t| t.as_str()).unwrap_or("<no type>").to_string();
+        if kind.ignored().contains(&type_name.as_str()) {
+            return;
+        }
+        *self.counts.entry(kind).or_default() += 1;
+        self.samples.entry(format!("{}: {}", kind.label(), type_name)).or_insert(raw);
+    }
+
+    fn summary(&self) -> Option<String> {
+        if self.counts.is_empty() {
+            return None;
+        }
+        let parts: Vec<String> = self
+            .counts
+            .iter()
+            .map(|(kind, n)| format!("{} unknown {}{}", n, kind.label(), if *n == 1 { "" } else { "s" }))
+            .collect();
+        Some(format!("saw {}", parts.join(", ")))
+    }
+}
+
+static UNKNOWN_EVENTS: Mutex<UnknownEvents> =
+    Mutex::new(UnknownEvents { counts: BTreeMap::new(), samples: BTreeMap::new() });
+
+/// Note an unrecognised value, given its raw JSON
+pub fn record(kind: UnknownKind, raw: serde_json::Value) {
+    if let Ok(mut events) = UNKNOWN_EVENTS.lock() {
+        events.record(kind, raw);
+    }
+}
+
+/// `saw 12 unknown claude events, 3 unknown codex items`, or None if everything parsed
+pub fn summary() -> Option<String> {
+    UNKNOWN_EVENTS.lock().ok()?.summary()
+}
+
+/// Write one raw sample per unknown type to `path` (--debug-unknown)
+pub fn write_samples(path: &Path) -> Result<()> {
+    let samples = match UNKNOWN_EVENTS.lock() {
+        Ok(events) => events.samples.clone(),
+        Err(_) => return Ok(()),
+    };
+    let json = serde_json::to_string_pretty(&samples)?;
+    std::fs::write(path, json + "\n").with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_summary_counts_each_kind() {
+        let mut events = UnknownEvents::default();
+        assert_eq!(events.summary(), None);
+
+        for _ in 0..2 {
+            events.record(UnknownKind::ClaudeEvent, json!({"type": "rate_limit", "n": 1}));
+        }
+        events.record(UnknownKind::CodexItem, json!({"type": "web_search"}));
+        assert_eq!(events.summary().unwrap(), "saw 2 unknown claude events, 1 unknown codex item");
+        assert_eq!(events.samples.len(), 2);
+        assert_eq!(events.samples["claude event: rate_limit"]["n"], 1);
+    }
+
+    #[test]
+    fn test_deliberately_ignored_events_are_not_counted() {
+        let mut events = UnknownEvents::default();
+        events.record(UnknownKind::ClaudeEvent, json!({"type": "system", "subtype": "init"}));
+        events.record(UnknownKind::CodexEvent, json!({"type": "turn.started"}));
+        assert_eq!(events.summary(), None);
+    }
+}
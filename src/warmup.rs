@@ -0,0 +1,101 @@
+//! `--warmup-context`: a lightweight repository overview for the first prompts
+//!
+//! Agents new to a repo spend their opening turns listing directories. This gives them
+//! the file tree (from `git ls-files`, so `.gitignore` is respected) and the top of the
+//! README up front, as a `## Repository Overview` context section.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::{git, log_line, truncate_head};
+
+/// How many directory levels of the tree to show
+const TREE_DEPTH: usize = 3;
+
+/// Repos with more files than this get no overview; the tree would be noise
+const MAX_FILES: usize = 20_000;
+
+/// Byte caps for the rendered tree and the README excerpt
+const TREE_BYTES: usize = 6_000;
+const README_BYTES: usize = 2_000;
+
+/// The overview section for the repository at `dir`, or None outside a repo or in a huge one
+pub fn repo_overview(dir: &Path) -> Option<String> {
+    let files = match git::listed_files(dir) {
+        Ok(files) => files,
+        Err(e) => {
+            log_line("system", &format!("--warmup-context skipped: {:#}", e));
+            return None;
+        }
+    };
+    if files.is_empty() {
+        return None;
+    }
+    if files.len() > MAX_FILES {
+        log_line("system", &format!("--warmup-context skipped: {} files is too many to summarize", files.len()));
+        return None;
+    }
+
+    let mut overview = format!(
+        "## Repository Overview\n\n{} files; tree to depth {}:\n\n```\n{}\n```",
+        files.len(),
+        TREE_DEPTH,
+        render_tree(&files, TREE_DEPTH, TREE_BYTES)
+    );
+    let readme = ["README.md", "README", "README.rst", "README.txt"].iter().find(|name| files.iter().any(|f| f == *name));
+    if let Some(content) = readme.and_then(|name| std::fs::read_to_string(dir.join(name)).ok()) {
+        if !content.trim().is_empty() {
+            overview.push_str(&format!("\n\n### {} (excerpt)\n\n{}", readme.unwrap(), truncate_head(content.trim(), README_BYTES)));
+        }
+    }
+    log_line("system", &format!("--warmup-context: {} files, {} byte overview", files.len(), overview.len()));
+    Some(overview)
+}
+
+/// An indented tree of `files`, `depth` levels deep, cut off after about `max_bytes`
+fn render_tree(files: &[String], depth: usize, max_bytes: usize) -> String {
+    // Path prefix -> whether it's a directory; sorting component lists keeps children under parents
+    let mut entries: BTreeMap<Vec<&str>, bool> = BTreeMap::new();
+    for file in files {
+        let parts: Vec<&str> = file.split('/').collect();
+        for level in 1..=parts.len().min(depth) {
+            entries.insert(parts[..level].to_vec(), level < parts.len());
+        }
+    }
+
+    let mut tree = String::new();
+    for (shown, (parts, is_dir)) in entries.iter().enumerate() {
+        let line = format!("{}{}{}\n", "  ".repeat(parts.len() - 1), parts[parts.len() - 1], if *is_dir { "/" } else { "" });
+        if tree.len() + line.len() > max_bytes {
+            tree.push_str(&format!("... ({} more)\n", entries.len() - shown));
+            break;
+        }
+        tree.push_str(&line);
+    }
+    tree.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    // render_tree() tests
+    #[test]
+    fn test_render_tree_nests_and_limits_depth() {
+        let tree = render_tree(&files(&["Cargo.toml", "src/main.rs", "src/a/b/c/deep.rs", "README.md"]), 3, 1000);
+        assert_eq!(tree, "Cargo.toml\nREADME.md\nsrc/\n  a/\n    b/\n  main.rs");
+    }
+
+    #[test]
+    fn test_render_tree_respects_byte_cap() {
+        let many: Vec<String> = (0..100).map(|i| format!("file{:03}.rs", i)).collect();
+        let tree = render_tree(&many, 3, 50);
+        assert!(tree.len() < 80);
+        assert!(tree.ends_with("more)"));
+        assert!(tree.starts_with("file000.rs\n"));
+    }
+}
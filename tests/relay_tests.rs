@@ -0,0 +1,279 @@
+// End-to-end relay tests: the real binary (and run_session) against examples/mock-agent
+
+use clap::Parser;
+use leonard::{Args, FinishReason, RelayEvent, Role, Termination, TranscriptEntry};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+/// The mock agent, built once per test run so it's never stale when only this target was rebuilt
+fn mock_agent() -> PathBuf {
+    static BUILT: OnceLock<PathBuf> = OnceLock::new();
+    BUILT
+        .get_or_init(|| {
+            let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+            let status = Command::new(cargo)
+                .args(["build", "--example", "mock-agent"])
+                .current_dir(env!("CARGO_MANIFEST_DIR"))
+                .status()
+                .expect("failed to run cargo build");
+            assert!(status.success(), "failed to build the mock agent");
+            let leonard = Path::new(env!("CARGO_BIN_EXE_leonard"));
+            leonard.with_file_name("examples").join(format!("mock-agent{}", std::env::consts::EXE_SUFFIX))
+        })
+        .clone()
+}
+
+/// A fresh directory holding `fixture` as fixture.json
+fn scratch_dir(name: &str, fixture: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("leonard-relay-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("fixture.json"), fixture).unwrap();
+    dir
+}
+
+/// Run a relay in `dir` with both agents played by the mock
+fn run_relay(dir: &Path, extra: &[&str]) -> Output {
+    let mock = mock_agent();
+    Command::new(env!("CARGO_BIN_EXE_leonard"))
+        .args(["--task", "Add a test", "--cwd"])
+        .arg(dir)
+        .arg("--driver-bin")
+        .arg(&mock)
+        .arg("--navigator-bin")
+        .arg(&mock)
+        .arg("--status-file")
+        .arg(dir.join("status.json"))
+        .args(extra)
+        .env("MOCK_AGENT_FIXTURE", dir.join("fixture.json"))
+        .env("ANTHROPIC_API_KEY", "mock")
+        .env("OPENAI_API_KEY", "mock")
+        .output()
+        .expect("failed to run leonard")
+}
+
+fn prompts(dir: &Path, backend: &str) -> Vec<String> {
+    (0..).map_while(|n| std::fs::read_to_string(dir.join(format!("{}-{}.prompt", backend, n))).ok()).collect()
+}
+
+fn status(dir: &Path) -> serde_json::Value {
+    serde_json::from_str(&std::fs::read_to_string(dir.join("status.json")).unwrap()).unwrap()
+}
+
+#[test]
+fn test_relay_stops_on_all_done() {
+    let dir = scratch_dir(
+        "all-done",
+        r#"{"claude": ["I'll add a test for parse()", "Added the error-path test"],
+            "codex": ["Also cover the error path", "ALL_DONE"]}"#,
+    );
+    let output = run_relay(&dir, &[]);
+    assert_eq!(output.status.code(), Some(0), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("done after 1 turn(s): all_done"));
+
+    let driver = prompts(&dir, "claude");
+    let navigator = prompts(&dir, "codex");
+    assert_eq!((driver.len(), navigator.len()), (2, 2));
+    assert!(driver[0].contains("Add a test"));
+    assert!(navigator[0].contains("I'll add a test for parse()"));
+    assert!(driver[1].contains("Also cover the error path"));
+    assert!(navigator[1].contains("Added the error-path test"));
+
+    let status = status(&dir);
+    assert_eq!(status["state"], "done");
+    assert_eq!(status["turn"], 1);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_relay_with_navigators_ends_on_a_quorum() {
+    // The navigators run concurrently, so which takes which reply varies; the vote counts don't
+    let dir = scratch_dir(
+        "navigators",
+        r#"{"claude": ["plan", "added tests"],
+            "codex": ["add tests", "ALL_DONE", "add tests", "ALL_DONE", "ALL_DONE", "one more nit"]}"#,
+    );
+    let output = run_relay(&dir, &["--navigators", "3", "--quorum", "2"]);
+    assert_eq!(output.status.code(), Some(0), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("running 3 navigators concurrently"));
+    assert!(stderr.contains("1 of 3 navigators signaled ALL_DONE (quorum 2)"));
+
+    let driver = prompts(&dir, "claude");
+    assert_eq!(driver.len(), 2);
+    assert_eq!(driver[1].matches("### Navigator ").count(), 2);
+    assert_eq!(driver[1].matches("add tests").count(), 2);
+
+    // Each navigator resumes its own session on the next turn
+    assert_eq!(prompts(&dir, "codex").len(), 6);
+    let mut resumed: Vec<String> = (3..6)
+        .map(|n| std::fs::read_to_string(dir.join(format!("codex-{}.args", n))).unwrap())
+        .map(|args| args.split_once("resume\n").unwrap().1.lines().next().unwrap().to_string())
+        .collect();
+    resumed.sort();
+    assert_eq!(resumed, ["mock-codex-0", "mock-codex-1", "mock-codex-2"]);
+    assert_eq!(status(&dir)["turn"], 1);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_relay_resumes_from_the_latest_checkpoint() {
+    // The navigator's second call fails, as if the run had died partway through turn 1
+    let dir = scratch_dir("checkpoint", r#"{"claude": ["plan", "added tests"], "codex": ["add tests"]}"#);
+    let checkpoints = dir.join("checkpoints");
+    let checkpoint_dir = checkpoints.to_str().unwrap();
+    let output = run_relay(&dir, &["--checkpoint-dir", checkpoint_dir]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(checkpoints.join("turn-0.json").exists());
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(checkpoints.join("turn-1.json")).unwrap()).unwrap();
+    assert_eq!(saved["driver_output"], "added tests");
+    assert_eq!(saved["navigator_output"], "add tests");
+    assert_eq!(saved["navigator_started"], true);
+
+    // The failed call used up the mock's second codex reply
+    let fixture = r#"{"claude": ["plan", "added tests"], "codex": ["add tests", "(crashed)", "ALL_DONE"]}"#;
+    std::fs::write(dir.join("fixture.json"), fixture).unwrap();
+    let output = run_relay(&dir, &["--checkpoint-dir", checkpoint_dir, "--resume-checkpoint"]);
+    assert_eq!(output.status.code(), Some(0), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("at turn 1"));
+    assert!(stderr.contains("done after 1 turn(s): all_done"));
+
+    // Neither turn 0 nor the driver's turn-1 reply is re-run; the navigator continues its session
+    assert_eq!(prompts(&dir, "claude").len(), 2);
+    let navigator = prompts(&dir, "codex");
+    assert_eq!(navigator.len(), 3);
+    assert!(navigator[2].contains("added tests"));
+    assert!(std::fs::read_to_string(dir.join("codex-2.args")).unwrap().contains("resume"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_relay_stops_at_max_turns_with_exit_code_2() {
+    let dir = scratch_dir(
+        "max-turns",
+        r#"{"claude": ["plan", "first fix", "second fix"], "codex": ["not yet", "still not", "no"]}"#,
+    );
+    let output = run_relay(&dir, &["--max-turns", "2"]);
+    assert_eq!(output.status.code(), Some(2), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("done after 2 turn(s): max_turns"));
+    assert_eq!(prompts(&dir, "claude").len(), 3);
+    assert_eq!(prompts(&dir, "codex").len(), 2);
+    assert_eq!(status(&dir)["state"], "done");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_confirm_before_edits_is_rejected_with_a_codex_driver() {
+    let dir = scratch_dir("confirm-reverse", r#"{"claude": ["ALL_DONE"], "codex": ["plan"]}"#);
+    let output = run_relay(&dir, &["--reverse", "--confirm-before-edits"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+    // Rejected before the codex driver could plan in a session it can never edit from
+    assert!(!dir.join("codex-0.args").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_relay_fails_with_exit_code_3_when_an_agent_fails() {
+    let dir = scratch_dir("agent-fails", r#"{"claude": ["plan"], "codex": []}"#);
+    let output = run_relay(&dir, &[]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no codex reply scripted"));
+    assert_eq!(status(&dir)["state"], "failed");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_relay_ends_at_the_deadline_mid_turn() {
+    let dir = scratch_dir("deadline", r#"{"claude": ["plan"], "codex": [{"sleep_secs": 30, "reply": "ALL_DONE"}]}"#);
+    let transcript = dir.join("transcript.json");
+    let started = std::time::Instant::now();
+    let output = run_relay(&dir, &["--deadline", "1", "--output", "sse", "--transcript", transcript.to_str().unwrap()]);
+    assert!(started.elapsed() < std::time::Duration::from_secs(20), "the hung navigator wasn't killed");
+    assert_eq!(output.status.code(), Some(2), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("done after 0 turn(s): deadline"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(r#""type":"done","turns":0,"reason":"deadline""#));
+    assert_eq!(status(&dir)["state"], "done");
+    // The interrupted turn isn't recorded as a failure
+    assert!(!std::fs::read_to_string(&transcript).unwrap().contains(r#""kind": "error""#));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_run_session_returns_the_outcome() {
+    let dir = scratch_dir("session", r#"{"claude": ["plan", "fixed"], "codex": ["fix the typo", "ALL_DONE"]}"#);
+    let mock = mock_agent();
+    let mock = mock.to_str().unwrap();
+    let args = Args::parse_from(["leonard", "--driver-bin", mock, "--navigator-bin", mock]);
+    // The other tests pass the fixture to their own leonard, or to the agents with --*-env
+    std::env::set_var("MOCK_AGENT_FIXTURE", dir.join("fixture.json"));
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let seen = events.clone();
+    let listener = Box::new(move |event: &RelayEvent| seen.borrow_mut().push(event.clone()));
+    let outcome = leonard::run_session(&args, Some("Fix the typo"), None, Some(listener)).await.unwrap();
+
+    assert!(matches!(outcome.termination, Termination::Finished(FinishReason::AllDone)));
+    assert_eq!(outcome.turns, 1);
+    let replies: Vec<(Role, &str)> = outcome
+        .messages
+        .iter()
+        .filter_map(|entry| match entry {
+            TranscriptEntry::Message { role, text, .. } => Some((*role, text.as_str())),
+            TranscriptEntry::Error { .. } => None,
+        })
+        .collect();
+    assert_eq!(
+        replies,
+        vec![(Role::Driver, "plan"), (Role::Navigator, "fix the typo"), (Role::Driver, "fixed"), (Role::Navigator, "ALL_DONE")]
+    );
+    assert_eq!(events.borrow().last(), Some(&RelayEvent::Done { turns: 1, reason: FinishReason::AllDone }));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_run_session_rejects_what_the_cli_rejects() {
+    let args = Args::parse_from(["leonard", "--navigators", "2", "--quorum", "3"]);
+    let err = leonard::run_session(&args, Some("Fix it"), None, Some(Box::new(|_: &RelayEvent| {}))).await.unwrap_err();
+    assert!(err.to_string().contains("--quorum 3 is more than the 2 navigator(s)"), "{}", err);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_each_run_session_has_its_own_deadline() {
+    let mock = mock_agent();
+    let mock = mock.to_str().unwrap();
+    // Each session passes its fixture through --*-env rather than the process environment
+    let session = |dir: &Path, extra: &[&str]| {
+        let env = format!("MOCK_AGENT_FIXTURE={}", dir.join("fixture.json").display());
+        let mut argv = vec!["leonard", "--driver-bin", mock, "--navigator-bin", mock];
+        argv.extend(["--driver-env", &env, "--navigator-env", &env]);
+        argv.extend(extra);
+        Args::parse_from(argv)
+    };
+
+    let hung = scratch_dir("session-deadline", r#"{"claude": ["plan"], "codex": [{"sleep_secs": 30, "reply": "ALL_DONE"}]}"#);
+    let outcome = leonard::run_session(&session(&hung, &["--deadline", "1"]), Some("Fix it"), None, Some(Box::new(|_: &RelayEvent| {})))
+        .await
+        .unwrap();
+    assert!(matches!(outcome.termination, Termination::Finished(FinishReason::Deadline)));
+
+    // A later session without --deadline isn't cut short by the first one's
+    let quick = scratch_dir("session-no-deadline", r#"{"claude": ["plan", "fixed"], "codex": ["fix it", "ALL_DONE"]}"#);
+    let outcome = leonard::run_session(&session(&quick, &[]), Some("Fix it"), None, Some(Box::new(|_: &RelayEvent| {})))
+        .await
+        .unwrap();
+    assert!(matches!(outcome.termination, Termination::Finished(FinishReason::AllDone)));
+    let _ = std::fs::remove_dir_all(&hung);
+    let _ = std::fs::remove_dir_all(&quick);
+}
+
+#[test]
+fn test_usage_error_exits_with_exit_code_3() {
+    let output = Command::new(env!("CARGO_BIN_EXE_leonard")).arg("--no-such-flag").output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+}